@@ -0,0 +1,84 @@
+use syn::{Attribute, LitInt};
+
+/// Options given by `#[packed(...)]` on the enum
+#[derive(Debug, Clone, Default)]
+pub struct EnumAttrs {
+    /// Variants with the same size and alignment share a bucket
+    pub share_buckets: bool,
+    /// Payloads that fit in an entry are stored there
+    pub inline: bool,
+    /// Variants whose payload is larger than this many bytes are boxed
+    pub box_above: Option<usize>,
+}
+
+impl EnumAttrs {
+    pub fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut out = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("packed")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("share_buckets") {
+                    out.share_buckets = true;
+                    Ok(())
+                } else if meta.path.is_ident("inline") {
+                    out.inline = true;
+                    Ok(())
+                } else if meta.path.is_ident("box_above") {
+                    let size: LitInt = meta.value()?.parse()?;
+                    out.box_above = Some(size.base10_parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown packed option"))
+                }
+            })?;
+        }
+        Ok(out)
+    }
+}
+
+/// Options given by `#[packed(...)]` on a variant
+#[derive(Debug, Clone, Default)]
+pub struct VariantAttrs {
+    /// The payload is stored in a box in its bucket
+    pub boxed: bool,
+}
+
+impl VariantAttrs {
+    pub fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut out = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("packed")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("boxed") {
+                    out.boxed = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown packed option"))
+                }
+            })?;
+        }
+        Ok(out)
+    }
+}
+
+/// Options given by `#[packed(...)]` on a field
+#[derive(Debug, Clone, Default)]
+pub struct FieldAttrs {
+    /// The field is stored after the other fields of its variant
+    pub tail: bool,
+}
+
+impl FieldAttrs {
+    pub fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut out = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("packed")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tail") {
+                    out.tail = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown packed option"))
+                }
+            })?;
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{method_ident, tail::Tail};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{DataEnum, Visibility};
+
+/// Generates a trait with one method per variant that receives the whole
+/// bucket of that variant, along with its dispatch from `Pack`. Variants with a
+/// tail are left out, since their records differ in size and can't be passed
+/// as a slice.
+pub fn batch(
+    vis: &Visibility,
+    enom: &Ident,
+    module: &Ident,
+    e: &DataEnum,
+    tails: &[Option<Tail>],
+) -> TokenStream {
+    let trait_ident = format_ident!("{}Batch", enom);
+    let variant_idents: Vec<_> = e
+        .variants
+        .iter()
+        .zip(tails)
+        .filter(|(_, tail)| tail.is_none())
+        .map(|(variant, _)| &variant.ident)
+        .collect();
+    let methods: Vec<_> = variant_idents
+        .iter()
+        .map(|ident| method_ident(ident))
+        .collect();
+    let trait_doc = format!(
+        "Processes the elements of a `Pack<{enom}>` one variant at a time. Variants with a tail have no method. See `Pack::for_each_batch`."
+    );
+    let method_docs = variant_idents
+        .iter()
+        .map(|ident| format!("Receives every `{enom}::{ident}` in bucket order"));
+
+    quote! {
+        #[doc = #trait_doc]
+        #[allow(dead_code)]
+        #vis trait #trait_ident {
+            #(
+            #[doc = #method_docs]
+            fn #methods(&mut self, items: &mut [#module::#variant_idents]);
+            )*
+        }
+
+        #[automatically_derived]
+        impl<B: #trait_ident + ?Sized> ::packed_enum::DispatchBatch<B> for #enom {
+            unsafe fn dispatch_batch(
+                batch: &mut B,
+                variant: Self::Variant,
+                data: *mut u8,
+                len: usize,
+            ) {
+                #[allow(unreachable_patterns)]
+                match variant {
+                    #(
+                    #module::Variant::#variant_idents => {
+                        let items = unsafe { ::std::slice::from_raw_parts_mut(data.cast(), len) };
+                        batch.#methods(items);
+                    }
+                    )*
+                    _ => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    attrs::{EnumAttrs, VariantAttrs},
+    tail::Tail,
+};
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::DataEnum;
+
+/// Decides which variants may be boxed, as a constant expression for each
+/// variant that is `None` if it never is. Variants with a tail already store
+/// only a pointer in their bucket, so they are never boxed.
+pub fn boxed(
+    attrs: &EnumAttrs,
+    e: &DataEnum,
+    tails: &[Option<Tail>],
+) -> syn::Result<Vec<Option<TokenStream>>> {
+    e.variants
+        .iter()
+        .zip(tails)
+        .map(|(variant, tail)| {
+            let ident = &variant.ident;
+            if tail.is_some() {
+                return Ok(None);
+            }
+            let boxed = VariantAttrs::parse(&variant.attrs)?.boxed;
+            if variant.fields.is_empty() {
+                return match boxed {
+                    true => Err(syn::Error::new_spanned(
+                        ident,
+                        "variants without fields cannot be boxed",
+                    )),
+                    false => Ok(None),
+                };
+            }
+            Ok(match (boxed, attrs.box_above) {
+                (true, _) => Some(quote! { true }),
+                (false, Some(size)) => Some(quote! { ::std::mem::size_of::<#ident>() > #size }),
+                (false, None) => None,
+            })
+        })
+        .collect()
+}
+
+/// Generates whether each variant is boxed and the layout of what it stores in
+/// its bucket, which is a pointer to the record for variants with a tail
+pub fn boxed_defs(
+    e: &DataEnum,
+    boxed: &[Option<TokenStream>],
+    tails: &[Option<Tail>],
+) -> TokenStream {
+    let variant_count = e.variants.len();
+    let variant_idents: Vec<_> = e.variants.iter().map(|variant| &variant.ident).collect();
+    let layouts = variant_idents
+        .iter()
+        .zip(tails)
+        .map(|(ident, tail)| match tail {
+            Some(_) => quote! {
+                (
+                    ::std::mem::size_of::<*mut u8>(),
+                    ::std::mem::align_of::<*mut u8>(),
+                )
+            },
+            None => quote! {
+                if BOXED[Variant::#ident as usize] {
+                    (
+                        ::std::mem::size_of::<::std::boxed::Box<#ident>>(),
+                        ::std::mem::align_of::<::std::boxed::Box<#ident>>(),
+                    )
+                } else {
+                    (
+                        ::std::mem::size_of::<#ident>(),
+                        ::std::mem::align_of::<#ident>(),
+                    )
+                }
+            },
+        });
+    let boxed = boxed
+        .iter()
+        .map(|boxed| boxed.clone().unwrap_or_else(|| quote! { false }));
+    quote! {
+        /// Whether each variant stores its payload in a box
+        pub const BOXED: [bool; #variant_count] = [#(#boxed),*];
+
+        /// The size and alignment of what each variant stores in its bucket
+        const LAYOUTS: [(usize, usize); #variant_count] = [#(#layouts),*];
+    }
+}
+
+/// Wraps the reads of a variant that may be boxed so that `data` points to the
+/// variant struct rather than its box, returning the owned, shared and mutable
+/// reads
+pub fn unbox_reads(
+    module: &Ident,
+    ident: &Ident,
+    boxed: Option<&TokenStream>,
+    (read_own, read_ref, read_mut): (TokenStream, TokenStream, TokenStream),
+) -> (TokenStream, TokenStream, TokenStream) {
+    if boxed.is_none() {
+        return (read_own, read_ref, read_mut);
+    }
+    let cond = quote! { #module::BOXED[#module::Variant::#ident as usize] };
+    let strukt = quote! { #module::#ident };
+    let boxed_ty = quote! { ::std::boxed::Box<#strukt> };
+    let read_own = quote! {
+        let boxed = #cond.then(|| unsafe { data.cast::<#boxed_ty>().read() });
+        let data = match &boxed {
+            ::std::option::Option::Some(boxed) => ::std::ptr::from_ref::<#strukt>(boxed).cast::<u8>(),
+            ::std::option::Option::None => data,
+        };
+        let out = { #read_own };
+        if let ::std::option::Option::Some(boxed) = boxed {
+            // The fields were moved out, so only free the allocation
+            let raw = ::std::boxed::Box::into_raw(boxed);
+            ::std::mem::drop(unsafe {
+                ::std::boxed::Box::from_raw(raw.cast::<::std::mem::ManuallyDrop<#strukt>>())
+            });
+        }
+        out
+    };
+    let read_ref = quote! {
+        let data = if #cond {
+            ::std::ptr::from_ref::<#strukt>(unsafe { &**data.cast::<#boxed_ty>() }).cast::<u8>()
+        } else {
+            data
+        };
+        #read_ref
+    };
+    let read_mut = quote! {
+        let data = if #cond {
+            ::std::ptr::from_mut::<#strukt>(unsafe { &mut **data.cast::<#boxed_ty>() }).cast::<u8>()
+        } else {
+            data
+        };
+        #read_mut
+    };
+    (read_own, read_ref, read_mut)
+}
+
+/// Generates moving the variant struct at `src` into its slot at `dst`
+pub fn store(module: &Ident, ident: &Ident, boxed: Option<&TokenStream>) -> TokenStream {
+    let copy = quote! {
+        let dst = dst.cast();
+        unsafe {
+            ::std::ptr::copy(src, dst, 1);
+        }
+    };
+    if boxed.is_none() {
+        return copy;
+    }
+    let strukt = quote! { #module::#ident };
+    quote! {
+        if #module::BOXED[#module::Variant::#ident as usize] {
+            let mut boxed = ::std::boxed::Box::<#strukt>::new_uninit();
+            unsafe {
+                ::std::ptr::copy(src, boxed.as_mut_ptr(), 1);
+                dst.cast::<::std::boxed::Box<#strukt>>().write(boxed.assume_init());
+            }
+        } else {
+            #copy
+        }
+    }
+}
+
+/// Generates dropping `len` slots of a variant starting at `data`
+pub fn drop_slots(module: &Ident, ident: &Ident, boxed: Option<&TokenStream>) -> TokenStream {
+    let drop = |ty: TokenStream| {
+        quote! {
+            let slice = ::std::ptr::slice_from_raw_parts_mut(data.cast::<#ty>(), len);
+            unsafe { ::std::ptr::drop_in_place(slice) };
+        }
+    };
+    let strukt = quote! { #module::#ident };
+    let plain = drop(strukt.clone());
+    if boxed.is_none() {
+        return plain;
+    }
+    let boxed = drop(quote! { ::std::boxed::Box<#strukt> });
+    quote! {
+        if #module::BOXED[#module::Variant::#ident as usize] {
+            #boxed
+        } else {
+            #plain
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{tail::Tail, IdentOrIndex};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{DataEnum, Field, Variant};
+
+/// Gets the names of the shared and mutable column types of a variant
+pub fn column_idents(variant: &Ident) -> (Ident, Ident) {
+    (
+        format_ident!("{}Column", variant),
+        format_ident!("{}ColumnMut", variant),
+    )
+}
+
+/// Generates the column types of each variant without a tail, which iterate
+/// over a single field across a whole bucket
+pub fn column_defs(e: &DataEnum, tails: &[Option<Tail>]) -> TokenStream {
+    e.variants
+        .iter()
+        .zip(tails)
+        .filter(|(_, tail)| tail.is_none())
+        .map(|(variant, _)| column_def(variant))
+        .collect()
+}
+
+fn column_def(variant: &Variant) -> TokenStream {
+    let Variant { ident, fields, .. } = variant;
+    let (column, column_mut) = column_idents(ident);
+    let field_idents: Vec<_> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| IdentOrIndex::from_ident_index(&field.ident, i))
+        .collect();
+    let methods: Vec<_> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => ident.clone(),
+            None => format_ident!("_{}", i),
+        })
+        .collect();
+    let tys: Vec<_> = fields.iter().map(|Field { ty, .. }| ty).collect();
+    let doc = format!("The fields of every `{ident}` in a bucket");
+    let doc_mut = format!("The mutable fields of every `{ident}` in a bucket");
+
+    quote! {
+        #[doc = #doc]
+        pub struct #column<'a>(pub &'a [#ident]);
+
+        impl<'a> #column<'a> {
+            #(
+            pub fn #methods(&self) -> impl ::std::iter::Iterator<Item = &'a #tys> + 'a {
+                self.0.iter().map(|item| &item.#field_idents)
+            }
+            )*
+        }
+
+        #[doc = #doc_mut]
+        pub struct #column_mut<'a>(pub &'a mut [#ident]);
+
+        impl<'a> #column_mut<'a> {
+            #(
+            pub fn #methods(&mut self) -> impl ::std::iter::Iterator<Item = &mut #tys> + '_ {
+                self.0.iter_mut().map(|item| &mut item.#field_idents)
+            }
+            )*
+        }
+    }
+}
@@ -1,3 +1,15 @@
+mod attrs;
+use attrs::EnumAttrs;
+
+mod boxed;
+use boxed::{boxed, boxed_defs, drop_slots, store, unbox_reads};
+
+mod tail;
+use tail::{
+    drop_records, record_defs, record_reads, tail_checks, tail_layout, tail_methods, tail_reads,
+    tails, write_record, Tail,
+};
+
 mod orm;
 use orm::Orm;
 
@@ -10,12 +22,27 @@ use variant_kind::VariantKind;
 mod ident_or_index;
 use ident_or_index::IdentOrIndex;
 
+mod ref_traits;
+use ref_traits::ref_traits;
+
+mod batch;
+use batch::batch;
+
+mod visitor;
+use visitor::visitor;
+
+mod column;
+use column::{column_defs, column_idents};
+
+mod pack_ext;
+use pack_ext::pack_ext;
+
 use proc_macro::TokenStream;
 use proc_macro2::{Ident, TokenStream as TokenStream2};
 use quote::{format_ident, quote, quote_spanned};
 use syn::{parse_macro_input, Data, DataEnum, DeriveInput, Field, Fields, Variant};
 
-#[proc_macro_derive(Packable)]
+#[proc_macro_derive(Packable, attributes(packed))]
 pub fn packable(input: TokenStream) -> TokenStream {
     let input: DeriveInput = parse_macro_input!(input);
     let span = input.ident.span();
@@ -30,22 +57,108 @@ pub fn packable(input: TokenStream) -> TokenStream {
 }
 
 fn packable_inner(input: DeriveInput) -> Result<TokenStream2, PackedError> {
-    let DeriveInput { data, ident, .. } = input;
+    let DeriveInput {
+        attrs,
+        data,
+        ident,
+        vis,
+        ..
+    } = input;
     let Data::Enum(e) = data else {
         return Err(PackedError::NotAnEnum);
     };
+    let attrs = EnumAttrs::parse(&attrs)?;
 
     let variant_count = e.variants.len();
     let module = format_ident!("{}_types", to_snake_case(&ident.to_string()));
     let variant_idents = variant_idents(&e);
     let arm_ignore = arm_ignore_all(&e);
     let arm_variables = arm_variables_all(&e);
-    let construct_struct = construct_struct_all(&module, &e);
-    let (read_own, read_ref, read_mut) = read_all(&ident, &module, &e).into_tuple();
-    let (defs_own, defs_ref, defs_mut) = defs_all(&e).into_tuple();
+    let tails = tails(&e)?;
+    let boxed = boxed(&attrs, &e, &tails)?;
+    let boxed_defs = boxed_defs(&e, &boxed, &tails);
+    let (read_own, read_ref, read_mut) = read_all(&ident, &module, &e, &tails).into_tuple();
+    let ((read_own, read_ref), read_mut): ((Vec<_>, Vec<_>), Vec<_>) = read_own
+        .into_iter()
+        .zip(read_ref)
+        .zip(read_mut)
+        .zip(variant_idents.iter().zip(boxed.iter().zip(&tails)))
+        .map(|(((own, r), m), (variant, (boxed, tail)))| {
+            let (own, r, m) = unbox_reads(&module, variant, boxed.as_ref(), (own, r, m));
+            let (own, r, m) = record_reads(&module, variant, tail.as_ref(), (own, r, m));
+            ((own, r), m)
+        })
+        .unzip();
+    let writes: Vec<_> = e
+        .variants
+        .iter()
+        .zip(boxed.iter().zip(&tails))
+        .map(|(variant, (boxed, tail))| match tail {
+            Some(tail) => write_record(&module, variant, tail),
+            None => {
+                let ident = &variant.ident;
+                let construct_struct = construct_struct(&module, variant);
+                let store = store(&module, ident, boxed.as_ref());
+                quote! {
+                    let strukt = ::std::mem::ManuallyDrop::new(#construct_struct);
+                    let strukt = <::std::mem::ManuallyDrop<#module::#ident> as ::std::ops::Deref>::deref(&strukt);
+                    let src = ::std::ptr::from_ref(strukt);
+                    #store
+                }
+            }
+        })
+        .collect();
+    let drops: Vec<_> = variant_idents
+        .iter()
+        .zip(boxed.iter().zip(&tails))
+        .map(|(variant, (boxed, tail))| match tail {
+            Some(_) => drop_records(&module, variant),
+            None => drop_slots(&module, variant, boxed.as_ref()),
+        })
+        .collect();
+    let (defs_own, defs_ref, defs_mut) = defs_all(&e, &tails).into_tuple();
+    let ref_traits = ref_traits(&e, &tails);
+    let batch = batch(&vis, &ident, &module, &e, &tails);
+    let visitor = visitor(&vis, &ident, &module, &e, &tails);
+    let column_defs = column_defs(&e, &tails);
+    let pack_ext = pack_ext(&vis, &ident, &module, &e, &tails);
+    let (bucket_defs, bucket_methods) = buckets(&attrs, &e, &tails);
+    let tail_checks = tail_checks(&tails);
+    let record_defs = record_defs(&e, &tails);
+    let tail_layout = tail_layout(&e, &tails);
+    let tail_methods = tail_methods(&ident, &e, &tails);
+    let inline = attrs.inline;
+    let payload_layouts: Vec<_> = variant_idents
+        .iter()
+        .zip(&tails)
+        .map(|(ident, tail)| match tail {
+            Some(_) => quote! { LAYOUTS[Self::#ident as usize] },
+            None => quote! {
+                (
+                    ::std::mem::size_of ::<#ident>(),
+                    ::std::mem::align_of::<#ident>(),
+                )
+            },
+        })
+        .collect();
+    // Records of variants with a tail are kept in one more bucket
+    let bucket_count = variant_count + usize::from(tails.iter().any(Option::is_some));
+    // Variants with a tail have unsized variant structs, which can't be
+    // moved in and out as payloads
+    let payloads: Vec<_> = variant_idents
+        .iter()
+        .zip(&tails)
+        .filter(|(_, tail)| tail.is_none())
+        .map(|(ident, _)| *ident)
+        .collect();
+    let (columns, columns_mut): (Vec<_>, Vec<_>) =
+        payloads.iter().map(|ident| column_idents(ident)).unzip();
 
     let out = quote! {
         mod #module {
+            #[allow(unused_imports)]
+            use super::*;
+
             #(#defs_own)*
 
             pub enum Ref<'a> {
@@ -56,6 +169,18 @@ fn packable_inner(input: DeriveInput) -> Result<TokenStream2, PackedError> {
                 #(#defs_mut),*
             }
 
+            #ref_traits
+
+            #column_defs
+
+            #boxed_defs
+
+            #bucket_defs
+
+            #tail_checks
+
+            #record_defs
+
             #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
             pub enum Variant {
                 #(#variant_idents,)*
@@ -67,29 +192,115 @@ fn packable_inner(input: DeriveInput) -> Result<TokenStream2, PackedError> {
                 }
 
                 fn size_align(&self) -> (usize, usize) {
+                    LAYOUTS[*self as usize]
+                }
+
+                fn payload_size_align(&self) -> (usize, usize) {
                     match self {
-                        #(
-                        Self::#variant_idents => (
-                            ::std::mem::size_of ::<#variant_idents>(),
-                            ::std::mem::align_of::<#variant_idents>(),
-                        ),
-                        )*
+                        #( Self::#variant_idents => #payload_layouts, )*
                     }
                 }
 
+                fn needs_drop(&self) -> bool {
+                    // Dropping through the enum runs its Drop impl, if any,
+                    // regardless of the payload
+                    BOXED[*self as usize]
+                        || <super::#ident as ::packed_enum::Packable>::HAS_DROP_IMPL
+                        || match self {
+                            #( Self::#variant_idents => ::std::mem::needs_drop::<#variant_idents>(), )*
+                        }
+                }
+
                 fn all() -> impl Iterator<Item = Self> {
                     [
                         #( Self::#variant_idents ),*
                     ].into_iter()
                 }
+
+                fn is_boxed(&self) -> bool {
+                    BOXED[*self as usize]
+                }
+
+                #tail_layout
+
+                #bucket_methods
+            }
+        }
+
+        #batch
+
+        #visitor
+
+        #pack_ext
+
+        #(
+        #[automatically_derived]
+        impl ::packed_enum::Payload<#ident> for #module::#payloads {
+            const VARIANT: #module::Variant = #module::Variant::#payloads;
+
+            type Column<'a> = #module::#columns<'a>;
+            type ColumnMut<'a> = #module::#columns_mut<'a>;
+
+            fn column(items: &[Self]) -> Self::Column<'_> {
+                #module::#columns(items)
+            }
+
+            fn column_mut(items: &mut [Self]) -> Self::ColumnMut<'_> {
+                #module::#columns_mut(items)
             }
         }
+        )*
 
         #[automatically_derived]
         impl ::packed_enum::Packable for #ident {
             const VARIANT_COUNT: usize = #variant_count;
 
+            const IS_COPY: bool = {
+                // The inherent constant takes precedence over the trait
+                // constant when its bounds hold
+                trait NotCopy {
+                    const IS_COPY: bool = false;
+                }
+                impl<T: ?Sized> NotCopy for T {}
+                struct IsCopy<T: ?Sized>(::std::marker::PhantomData<T>);
+                impl<T: ?Sized + ::std::marker::Copy> IsCopy<T> {
+                    const IS_COPY: bool = true;
+                }
+                <IsCopy<#ident>>::IS_COPY
+            };
+
+            const HAS_DROP_IMPL: bool = {
+                trait NoDropImpl {
+                    const HAS_DROP_IMPL: bool = false;
+                }
+                impl<T: ?Sized> NoDropImpl for T {}
+                struct HasDropImpl<T: ?Sized>(::std::marker::PhantomData<T>);
+                #[allow(drop_bounds)]
+                impl<T: ?Sized + ::std::ops::Drop> HasDropImpl<T> {
+                    const HAS_DROP_IMPL: bool = true;
+                }
+                <HasDropImpl<#ident>>::HAS_DROP_IMPL
+            };
+
+            const SHARES_BUCKETS: bool = #module::SHARES_BUCKETS;
+
+            const INLINE_PAYLOADS: bool = #inline;
+
             type Variant = #module::Variant;
+
+            type Buckets = [::packed_enum::ByteVec; #bucket_count];
+            const EMPTY_BUCKETS: Self::Buckets =
+                [const { ::packed_enum::ByteVec::new() }; #bucket_count];
+
+            type Array<V> = [V; #variant_count];
+
+            fn array_from_fn<V>(f: impl FnMut(usize) -> V) -> Self::Array<V> {
+                ::std::array::from_fn(f)
+            }
+
+            type Bits = [u64; #variant_count.div_ceil(64)];
+            const EMPTY_BITS: Self::Bits = [0; #variant_count.div_ceil(64)];
+
             type Ref<'a> = #module::Ref<'a>;
             type Mut<'a> = #module::Mut<'a>;
 
@@ -101,6 +312,34 @@ fn packable_inner(input: DeriveInput) -> Result<TokenStream2, PackedError> {
                 }
             }
 
+            unsafe fn drop_in_place(variant: Self::Variant, data: *mut u8, len: usize) {
+                if Self::HAS_DROP_IMPL {
+                    let (size, _) = ::packed_enum::Variant::size_align(&variant);
+                    for i in 0..len {
+                        let data = unsafe { data.add(i * size) };
+                        ::std::mem::drop(unsafe { Self::read(variant, data) });
+                    }
+                    return;
+                }
+                match variant {
+                    #(
+                    #module::Variant::#variant_idents => {
+                        #drops
+                    }
+                    )*
+                }
+            }
+
+            #tail_methods
+
+            fn to_ref(&self) -> Self::Ref<'_> {
+                match self {
+                    #(
+                    #ident::#variant_idents #arm_variables => #module::Ref::#variant_idents #arm_variables,
+                    )*
+                }
+            }
+
             unsafe fn read(variant: Self::Variant, data: *const u8) -> Self {
                 match variant {
                     #( #module::Variant::#variant_idents => { #read_own } ),*
@@ -127,13 +366,7 @@ fn packable_inner(input: DeriveInput) -> Result<TokenStream2, PackedError> {
                 match me {
                     #(
                     #ident::#variant_idents #arm_variables => {
-                        let strukt = ::std::mem::ManuallyDrop::new(#construct_struct);
-                        let strukt = <::std::mem::ManuallyDrop<#module::#variant_idents> as ::std::ops::Deref>::deref(&strukt);
-                        let src = ::std::ptr::from_ref(strukt);
-                        let dst = dst.cast();
-                        unsafe {
-                            ::std::ptr::copy(src, dst, 1);
-                        }
+                        #writes
                     },
                     )*
                 };
@@ -149,11 +382,77 @@ fn packable_inner(input: DeriveInput) -> Result<TokenStream2, PackedError> {
     Ok(out)
 }
 
-fn construct_struct_all(module: &Ident, e: &DataEnum) -> Vec<TokenStream2> {
-    e.variants
-        .iter()
-        .map(|variant| construct_struct(module, variant))
-        .collect()
+/// Generates the bucket assignment of each variant and the `Variant` methods
+/// reading it
+fn buckets(
+    attrs: &EnumAttrs,
+    e: &DataEnum,
+    tails: &[Option<Tail>],
+) -> (TokenStream2, TokenStream2) {
+    if !attrs.share_buckets {
+        return (
+            quote! { pub const SHARES_BUCKETS: bool = false; },
+            quote! {},
+        );
+    }
+
+    let variant_count = e.variants.len();
+    let has_tail = tails.iter().map(Option::is_some);
+    let defs = quote! {
+        /// The bucket of each variant, which is the first variant storing
+        /// something of the same size and alignment. Variants with a tail
+        /// keep a bucket of their own so that their records can be found
+        /// without the entries.
+        const BUCKETS: [usize; #variant_count] = {
+            let layouts = LAYOUTS;
+            let tails = [#(#has_tail),*];
+            let mut buckets = [0; #variant_count];
+            let mut i = 0;
+            while i < #variant_count {
+                let mut j = 0;
+                while !tails[i]
+                    && (tails[j] || layouts[j].0 != layouts[i].0 || layouts[j].1 != layouts[i].1)
+                {
+                    j += 1;
+                }
+                buckets[i] = if tails[i] { i } else { j };
+                i += 1;
+            }
+            buckets
+        };
+
+        /// Whether each variant shares its bucket with another
+        const SHARED: [bool; #variant_count] = {
+            let mut shared = [false; #variant_count];
+            let mut i = 0;
+            while i < #variant_count {
+                if BUCKETS[i] != i {
+                    shared[i] = true;
+                    shared[BUCKETS[i]] = true;
+                }
+                i += 1;
+            }
+            shared
+        };
+
+        pub const SHARES_BUCKETS: bool = {
+            let mut i = 0;
+            while i < #variant_count && !SHARED[i] {
+                i += 1;
+            }
+            i < #variant_count
+        };
+    };
+    let methods = quote! {
+        fn bucket_index(&self) -> usize {
+            BUCKETS[*self as usize]
+        }
+
+        fn shares_bucket(&self) -> bool {
+            SHARED[*self as usize]
+        }
+    };
+    (defs, methods)
 }
 
 fn construct_struct(module: &Ident, variant: &Variant) -> TokenStream2 {
@@ -172,7 +471,7 @@ fn construct_struct(module: &Ident, variant: &Variant) -> TokenStream2 {
     }
 }
 
-fn field_idents(fields: &Fields) -> Vec<IdentOrIndex> {
+fn field_idents(fields: &Fields) -> Vec<IdentOrIndex<'_>> {
     fields
         .iter()
         .enumerate()
@@ -201,8 +500,12 @@ fn arm_variables_all(e: &DataEnum) -> Vec<TokenStream2> {
 }
 
 fn arm_variables(variant: &Variant) -> TokenStream2 {
+    arm_bindings(variant, "field")
+}
+
+fn arm_bindings(variant: &Variant, prefix: &str) -> TokenStream2 {
     let Variant { fields, .. } = variant;
-    let field_variables = field_variables(&variant.fields);
+    let field_variables = field_bindings(&variant.fields, prefix);
     if fields.is_empty() {
         quote! {}
     } else if is_tuple(fields) {
@@ -214,26 +517,36 @@ fn arm_variables(variant: &Variant) -> TokenStream2 {
 }
 
 fn field_variables(fields: &Fields) -> Vec<Ident> {
+    field_bindings(fields, "field")
+}
+
+fn field_bindings(fields: &Fields, prefix: &str) -> Vec<Ident> {
     fields
         .iter()
         .enumerate()
-        .map(|(i, _)| format_ident!("field_{}", i))
+        .map(|(i, _)| format_ident!("{}_{}", prefix, i))
         .collect()
 }
 
-fn read_all(enom: &Ident, module: &Ident, e: &DataEnum) -> Orm<Vec<TokenStream2>> {
+fn read_all(
+    enom: &Ident,
+    module: &Ident,
+    e: &DataEnum,
+    tails: &[Option<Tail>],
+) -> Orm<Vec<TokenStream2>> {
     e.variants
         .iter()
-        .map(|variant| read(enom, module, variant))
+        .zip(tails)
+        .map(|(variant, tail)| read(enom, module, variant, tail.as_ref()))
         .collect()
 }
 
-fn read(enom: &Ident, module: &Ident, variant: &Variant) -> Orm<TokenStream2> {
+fn read(enom: &Ident, module: &Ident, variant: &Variant, tail: Option<&Tail>) -> Orm<TokenStream2> {
     let Variant { ident, fields, .. } = variant;
     if fields.is_empty() {
         read_empty(enom, ident)
     } else {
-        read_full(enom, module, ident, fields)
+        read_full(enom, module, ident, fields, tail)
     }
 }
 
@@ -245,8 +558,14 @@ fn read_empty(enom: &Ident, variant: &Ident) -> Orm<TokenStream2> {
     )
 }
 
-fn read_full(enom: &Ident, module: &Ident, variant: &Ident, fields: &Fields) -> Orm<TokenStream2> {
-    let (read_own, read_ref, read_mut) = field_reads(module, variant, fields).into_tuple();
+fn read_full(
+    enom: &Ident,
+    module: &Ident,
+    variant: &Ident,
+    fields: &Fields,
+    tail: Option<&Tail>,
+) -> Orm<TokenStream2> {
+    let (read_own, read_ref, read_mut) = field_reads(module, variant, fields, tail).into_tuple();
     Orm::new(
         quote! { #enom    ::#variant { #(#read_own),* } },
         quote! { Self::Ref::#variant { #(#read_ref),* } },
@@ -254,11 +573,23 @@ fn read_full(enom: &Ident, module: &Ident, variant: &Ident, fields: &Fields) ->
     )
 }
 
-fn field_reads(module: &Ident, variant: &Ident, fields: &Fields) -> Orm<Vec<TokenStream2>> {
+fn field_reads(
+    module: &Ident,
+    variant: &Ident,
+    fields: &Fields,
+    tail: Option<&Tail>,
+) -> Orm<Vec<TokenStream2>> {
     fields
         .iter()
         .enumerate()
-        .map(|(i, field)| field_read(module, variant, field, i))
+        .map(|(i, field)| match tail.filter(|tail| tail.index == i) {
+            Some(tail) => {
+                let field_ident = IdentOrIndex::from_ident_index(&field.ident, i);
+                let (own, r, m) = tail_reads(tail, &field_ident);
+                Orm::new(own, r, m)
+            }
+            None => field_read(module, variant, field, i),
+        })
         .collect()
 }
 
@@ -278,16 +609,29 @@ fn field_read(module: &Ident, variant: &Ident, field: &Field, i: usize) -> Orm<T
     )
 }
 
-fn defs_all(e: &DataEnum) -> Orm<Vec<TokenStream2>> {
-    e.variants.iter().map(variant_defs).collect()
+fn defs_all(e: &DataEnum, tails: &[Option<Tail>]) -> Orm<Vec<TokenStream2>> {
+    e.variants
+        .iter()
+        .zip(tails)
+        .map(|(variant, tail)| variant_defs(variant, tail.as_ref()))
+        .collect()
 }
 
-fn variant_defs(variant: &Variant) -> Orm<TokenStream2> {
+fn variant_defs(variant: &Variant, tail: Option<&Tail>) -> Orm<TokenStream2> {
     let Variant { ident, fields, .. } = variant;
 
-    let fields_orm: Orm<Vec<_>> = fields.iter().map(field_orm).collect();
+    let fields_orm: Orm<Vec<_>> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match tail.filter(|tail| tail.index == i) {
+            Some(tail) => tail_orm(field, tail),
+            None => field_orm(field),
+        })
+        .collect();
     let (fields_own, fields_ref, fields_mut) = fields_orm.into_tuple();
 
+    // The tail is the unsized last field, which must come after the others
+    let repr = tail.map(|_| quote! { #[repr(C)] });
     if fields.is_empty() {
         Orm::new(
             quote! { pub struct #ident; },
@@ -296,13 +640,13 @@ fn variant_defs(variant: &Variant) -> Orm<TokenStream2> {
         )
     } else if is_tuple(fields) {
         Orm::new(
-            quote! { pub struct #ident (#(#fields_own),*); },
+            quote! { #repr pub struct #ident (#(#fields_own),*); },
             quote! { #ident(#(#fields_ref),*) },
             quote! { #ident(#(#fields_mut),*) },
         )
     } else {
         Orm::new(
-            quote! { pub struct #ident { #(#fields_own),* } },
+            quote! { #repr pub struct #ident { #(#fields_own),* } },
             quote! { #ident { #(#fields_ref),* } },
             quote! { #ident { #(#fields_mut),* } },
         )
@@ -325,6 +669,24 @@ fn field_orm(field: &Field) -> Orm<TokenStream2> {
     }
 }
 
+/// Generates the field for a tail, which is the unsized last field of the
+/// variant struct and is borrowed as a slice
+fn tail_orm(field: &Field, tail: &Tail) -> Orm<TokenStream2> {
+    let borrowed = tail.borrowed();
+    match &field.ident {
+        Some(ident) => Orm::new(
+            quote! { pub #ident:         #borrowed },
+            quote! {     #ident: &'a     #borrowed },
+            quote! {     #ident: &'a mut #borrowed },
+        ),
+        None => Orm::new(
+            quote! { pub         #borrowed },
+            quote! {     &'a     #borrowed },
+            quote! {     &'a mut #borrowed },
+        ),
+    }
+}
+
 fn to_snake_case(s: &str) -> String {
     let mut chars = s.chars();
     let mut out = String::new();
@@ -339,6 +701,23 @@ fn to_snake_case(s: &str) -> String {
     out
 }
 
+/// Converts a variant name into a method name, escaping keywords
+fn method_ident(ident: &Ident) -> Ident {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do",
+        "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in",
+        "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+        "return", "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe",
+        "unsized", "use", "virtual", "where", "while", "yield",
+    ];
+    let name = to_snake_case(&ident.to_string());
+    if KEYWORDS.contains(&name.as_str()) {
+        Ident::new_raw(&name, ident.span())
+    } else {
+        Ident::new(&name, ident.span())
+    }
+}
+
 fn is_tuple(fields: &Fields) -> bool {
     fields
         .iter()
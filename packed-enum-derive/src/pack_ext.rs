@@ -0,0 +1,148 @@
+use crate::{field_bindings, is_tuple, method_ident, tail::Tail};
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{DataEnum, Field, Variant, Visibility};
+
+/// Generates an extension trait for `Pack` with named methods per variant
+pub fn pack_ext(
+    vis: &Visibility,
+    enom: &Ident,
+    module: &Ident,
+    e: &DataEnum,
+    tails: &[Option<Tail>],
+) -> TokenStream {
+    let trait_ident = format_ident!("{}PackExt", enom);
+    let trait_doc = format!("Per-variant methods for `Pack<{enom}>`");
+    let (decls, defs): (Vec<_>, Vec<_>) = e
+        .variants
+        .iter()
+        .zip(tails)
+        .map(|(variant, tail)| variant_methods(enom, module, variant, tail.is_some()))
+        .unzip();
+
+    quote! {
+        #[doc = #trait_doc]
+        #[allow(dead_code)]
+        #vis trait #trait_ident {
+            #(#decls)*
+        }
+
+        #[automatically_derived]
+        impl<L: ::packed_enum::EntryLayout> #trait_ident for ::packed_enum::Pack<#enom, L> {
+            #(#defs)*
+        }
+    }
+}
+
+/// Generates the methods of one variant. A variant with a tail has an unsized
+/// variant struct, which can't be moved in or out as a payload, so it only
+/// gets `push_` and `count_`, and is pushed as an enum value.
+fn variant_methods(
+    enom: &Ident,
+    module: &Ident,
+    variant: &Variant,
+    has_tail: bool,
+) -> (TokenStream, TokenStream) {
+    let Variant { ident, fields, .. } = variant;
+    let name = method_ident(ident);
+    let unraw = name.to_string().trim_start_matches("r#").to_string();
+    let push = format_ident!("push_{}", unraw);
+    let extend = format_ident!("extend_{}", unraw);
+    let append = format_ident!("append_{}", unraw);
+    let iter = format_ident!("iter_{}", unraw);
+    let count = format_ident!("count_{}", unraw);
+    let last = format_ident!("last_{}", unraw);
+
+    let params: Vec<_> = match fields.iter().next() {
+        Some(Field { ident: Some(_), .. }) => fields
+            .iter()
+            .filter_map(|field| field.ident.as_ref())
+            .map(|name| match name.to_string().strip_prefix("r#") {
+                Some(raw) => Ident::new_raw(raw, Span::call_site()),
+                None => Ident::new(&name.to_string(), Span::call_site()),
+            })
+            .collect(),
+        _ => field_bindings(fields, &unraw),
+    };
+    let tys: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let construct = |path| {
+        if fields.is_empty() {
+            quote! { #path::#ident }
+        } else if is_tuple(fields) {
+            quote! { #path::#ident(#(#params),*) }
+        } else {
+            quote! { #path::#ident { #(#params),* } }
+        }
+    };
+
+    let push_doc = format!("Appends a `{enom}::{ident}` from its fields");
+    let extend_doc = format!("Appends a `{enom}::{ident}` for each payload");
+    let append_doc = format!("Appends a `{enom}::{ident}` for each payload, emptying the `Vec`");
+    let iter_doc = format!("Iterates over the payload of every `{enom}::{ident}` in order");
+    let count_doc = format!("Counts the elements that are `{enom}::{ident}`");
+    let last_doc = format!("Gets the payload of the last `{enom}::{ident}`");
+
+    if has_tail {
+        let element = construct(enom);
+        let decls = quote! {
+            #[doc = #push_doc]
+            fn #push(&mut self, #(#params: #tys),*) -> #module::Mut<'_>;
+            #[doc = #count_doc]
+            fn #count(&self) -> usize;
+        };
+        let defs = quote! {
+            fn #push(&mut self, #(#params: #tys),*) -> #module::Mut<'_> {
+                self.push(#element)
+            }
+
+            fn #count(&self) -> usize {
+                self.count_of(#module::Variant::#ident)
+            }
+        };
+        return (decls, defs);
+    }
+    let payload = construct(module);
+
+    let decls = quote! {
+        #[doc = #push_doc]
+        fn #push(&mut self, #(#params: #tys),*) -> #module::Mut<'_>;
+        #[doc = #extend_doc]
+        fn #extend(&mut self, items: impl ::std::iter::IntoIterator<Item = #module::#ident>);
+        #[doc = #append_doc]
+        fn #append(&mut self, items: &mut ::std::vec::Vec<#module::#ident>);
+        #[doc = #iter_doc]
+        fn #iter(&self) -> impl ::std::iter::Iterator<Item = &#module::#ident>;
+        #[doc = #count_doc]
+        fn #count(&self) -> usize;
+        #[doc = #last_doc]
+        fn #last(&self) -> ::std::option::Option<&#module::#ident>;
+    };
+
+    let defs = quote! {
+        fn #push(&mut self, #(#params: #tys),*) -> #module::Mut<'_> {
+            self.push_payload(#payload)
+        }
+
+        fn #extend(&mut self, items: impl ::std::iter::IntoIterator<Item = #module::#ident>) {
+            self.extend_payloads(items)
+        }
+
+        fn #append(&mut self, items: &mut ::std::vec::Vec<#module::#ident>) {
+            self.append_payloads(items)
+        }
+
+        fn #iter(&self) -> impl ::std::iter::Iterator<Item = &#module::#ident> {
+            self.payloads::<#module::#ident>()
+        }
+
+        fn #count(&self) -> usize {
+            self.count_of(#module::Variant::#ident)
+        }
+
+        fn #last(&self) -> ::std::option::Option<&#module::#ident> {
+            self.last_payload::<#module::#ident>()
+        }
+    };
+
+    (decls, defs)
+}
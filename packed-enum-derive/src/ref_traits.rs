@@ -0,0 +1,183 @@
+use crate::{arm_bindings, field_bindings, is_tuple, tail::Tail};
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{DataEnum, Variant};
+
+/// Generates the standard traits for the `Ref` enum
+///
+/// Each impl is bounded on `&'a Field: Trait` for every field type rather
+/// than derived. Since those bounds mention `'a`, an impl whose bounds don't
+/// hold is simply unavailable instead of being a compile error. Tails are
+/// bounded as the slice they are borrowed as.
+pub fn ref_traits(e: &DataEnum, tails: &[Option<Tail>]) -> TokenStream {
+    let tys: Vec<_> = e
+        .variants
+        .iter()
+        .zip(tails)
+        .flat_map(|(variant, tail)| {
+            variant.fields.iter().enumerate().map(move |(i, field)| {
+                match tail.as_ref().filter(|tail| tail.index == i) {
+                    Some(tail) => tail.borrowed(),
+                    None => field.ty.to_token_stream(),
+                }
+            })
+        })
+        .collect();
+    let bindings: Vec<_> = e
+        .variants
+        .iter()
+        .map(|variant| arm_bindings(variant, "field"))
+        .collect();
+    let others: Vec<_> = e
+        .variants
+        .iter()
+        .map(|variant| arm_bindings(variant, "other"))
+        .collect();
+    let variant_idents: Vec<_> = e.variants.iter().map(|variant| &variant.ident).collect();
+    let discriminants = 0..e.variants.len();
+    let debug = e.variants.iter().map(debug);
+    let (fields, other_fields): (Vec<_>, Vec<_>) = e
+        .variants
+        .iter()
+        .map(|variant| {
+            (
+                field_bindings(&variant.fields, "field"),
+                field_bindings(&variant.fields, "other"),
+            )
+        })
+        .unzip();
+
+    quote! {
+        impl<'a> Ref<'a> {
+            fn discriminant(&self) -> usize {
+                match self {
+                    #( Self::#variant_idents { .. } => #discriminants, )*
+                }
+            }
+        }
+
+        impl<'a> ::std::fmt::Debug for Ref<'a>
+        where
+            #( &'a #tys: ::std::fmt::Debug, )*
+        {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                match self {
+                    #( Self::#variant_idents #bindings => #debug, )*
+                }
+            }
+        }
+
+        impl<'a> ::std::cmp::PartialEq for Ref<'a>
+        where
+            #( &'a #tys: ::std::cmp::PartialEq, )*
+        {
+            #[allow(unreachable_patterns)]
+            fn eq(&self, other: &Self) -> bool {
+                match (self, other) {
+                    #(
+                    (Self::#variant_idents #bindings, Self::#variant_idents #others) => {
+                        true #( && #fields == #other_fields )*
+                    }
+                    )*
+                    _ => false,
+                }
+            }
+        }
+
+        impl<'a> ::std::cmp::Eq for Ref<'a>
+        where
+            #( &'a #tys: ::std::cmp::Eq, )*
+        {
+        }
+
+        impl<'a> ::std::cmp::PartialOrd for Ref<'a>
+        where
+            #( &'a #tys: ::std::cmp::PartialOrd, )*
+        {
+            #[allow(unreachable_patterns)]
+            fn partial_cmp(&self, other: &Self) -> ::std::option::Option<::std::cmp::Ordering> {
+                match (self, other) {
+                    #(
+                    (Self::#variant_idents #bindings, Self::#variant_idents #others) => {
+                        #(
+                        match ::std::cmp::PartialOrd::partial_cmp(#fields, #other_fields) {
+                            ::std::option::Option::Some(::std::cmp::Ordering::Equal) => {}
+                            ordering => return ordering,
+                        }
+                        )*
+                        ::std::option::Option::Some(::std::cmp::Ordering::Equal)
+                    }
+                    )*
+                    _ => ::std::cmp::PartialOrd::partial_cmp(
+                        &self.discriminant(),
+                        &other.discriminant(),
+                    ),
+                }
+            }
+        }
+
+        impl<'a> ::std::cmp::Ord for Ref<'a>
+        where
+            #( &'a #tys: ::std::cmp::Ord, )*
+        {
+            #[allow(unreachable_patterns)]
+            fn cmp(&self, other: &Self) -> ::std::cmp::Ordering {
+                match (self, other) {
+                    #(
+                    (Self::#variant_idents #bindings, Self::#variant_idents #others) => {
+                        #(
+                        match ::std::cmp::Ord::cmp(#fields, #other_fields) {
+                            ::std::cmp::Ordering::Equal => {}
+                            ordering => return ordering,
+                        }
+                        )*
+                        ::std::cmp::Ordering::Equal
+                    }
+                    )*
+                    _ => ::std::cmp::Ord::cmp(&self.discriminant(), &other.discriminant()),
+                }
+            }
+        }
+
+        impl<'a> ::std::hash::Hash for Ref<'a>
+        where
+            #( &'a #tys: ::std::hash::Hash, )*
+        {
+            fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+                ::std::hash::Hash::hash(&self.discriminant(), state);
+                match self {
+                    #(
+                    Self::#variant_idents #bindings => {
+                        #( ::std::hash::Hash::hash(#fields, state); )*
+                    }
+                    )*
+                }
+            }
+        }
+    }
+}
+
+fn debug(variant: &Variant) -> TokenStream {
+    let Variant { ident, fields, .. } = variant;
+    let name = ident.to_string();
+    let field_variables = field_bindings(fields, "field");
+    if fields.is_empty() {
+        quote! { f.write_str(#name) }
+    } else if is_tuple(fields) {
+        quote! {
+            f.debug_tuple(#name)
+                #( .field(#field_variables) )*
+                .finish()
+        }
+    } else {
+        let names = fields
+            .iter()
+            .filter_map(|field| field.ident.as_ref())
+            .map(|ident| ident.to_string());
+        quote! {
+            f.debug_struct(#name)
+                #( .field(#names, #field_variables) )*
+                .finish()
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use crate::{
+    attrs::{FieldAttrs, VariantAttrs},
+    field_variables, IdentOrIndex,
+};
+use proc_macro2::{Ident, TokenStream};
+use quote::{quote, quote_spanned};
+use syn::{
+    parse_quote, spanned::Spanned, DataEnum, GenericArgument, PathArguments, Type, TypePath,
+    Variant,
+};
+
+/// A `Vec` or `String` field marked `#[packed(tail)]`, whose elements are
+/// stored right after the other fields of its variant
+pub struct Tail {
+    /// The index of the field, which is always the last one
+    pub index: usize,
+    /// The type of each element
+    pub elem: Type,
+    /// Whether the field is a `String`, which is borrowed as a `str`
+    pub string: bool,
+}
+
+impl Tail {
+    /// Gets the type the field is borrowed as in `Ref` and `Mut`
+    pub fn borrowed(&self) -> TokenStream {
+        let elem = &self.elem;
+        match self.string {
+            true => quote! { str },
+            false => quote! { [#elem] },
+        }
+    }
+
+    /// Gets the pattern binding the tail of a value of the variant to `tail`
+    fn pattern(&self, enom: &Ident, variant: &Variant) -> TokenStream {
+        let ident = &variant.ident;
+        match &variant
+            .fields
+            .iter()
+            .nth(self.index)
+            .and_then(|f| f.ident.as_ref())
+        {
+            Some(name) => quote! { #enom::#ident { #name: tail, .. } },
+            None => quote! { #enom::#ident(.., tail) },
+        }
+    }
+}
+
+/// Finds the tail of each variant, if it has one
+pub fn tails(e: &DataEnum) -> syn::Result<Vec<Option<Tail>>> {
+    e.variants
+        .iter()
+        .map(|variant| {
+            let mut tail = None;
+            for (index, field) in variant.fields.iter().enumerate() {
+                if !FieldAttrs::parse(&field.attrs)?.tail {
+                    continue;
+                }
+                if index + 1 != variant.fields.len() {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "the tail must be the last field",
+                    ));
+                }
+                if is_slice(&field.ty) {
+                    return Err(syn::Error::new_spanned(
+                        &field.ty,
+                        "the tail cannot be a slice; use `Vec<E>` or `String`, whose \
+                         elements are stored in the record and borrowed as `&[E]` or `&str`",
+                    ));
+                }
+                let Some((elem, string)) = tail_elem(&field.ty) else {
+                    return Err(syn::Error::new_spanned(
+                        &field.ty,
+                        "the tail must be a `Vec<E>` or a `String`",
+                    ));
+                };
+                tail = Some(Tail {
+                    index,
+                    elem,
+                    string,
+                });
+            }
+            if tail.is_some() && VariantAttrs::parse(&variant.attrs)?.boxed {
+                return Err(syn::Error::new_spanned(
+                    &variant.ident,
+                    "variants with a tail cannot be boxed",
+                ));
+            }
+            Ok(tail)
+        })
+        .collect()
+}
+
+/// Whether a type is a slice or `str`, either bare or behind a reference or
+/// `Box`
+fn is_slice(ty: &Type) -> bool {
+    match ty {
+        Type::Slice(_) => true,
+        Type::Path(TypePath { qself: None, path }) => {
+            let Some(segment) = path.segments.last() else {
+                return false;
+            };
+            match &segment.arguments {
+                PathArguments::None => segment.ident == "str",
+                PathArguments::AngleBracketed(args) if segment.ident == "Box" => {
+                    args.args.iter().any(|arg| match arg {
+                        GenericArgument::Type(ty) => is_slice(ty),
+                        _ => false,
+                    })
+                }
+                _ => false,
+            }
+        }
+        Type::Reference(reference) => is_slice(&reference.elem),
+        Type::Paren(paren) => is_slice(&paren.elem),
+        Type::Group(group) => is_slice(&group.elem),
+        _ => false,
+    }
+}
+
+/// Gets the element type of a `Vec` or `String` and whether it is a `String`
+fn tail_elem(ty: &Type) -> Option<(Type, bool)> {
+    let Type::Path(TypePath { qself: None, path }) = ty else {
+        return None;
+    };
+    let segment = path.segments.last()?;
+    match &segment.arguments {
+        PathArguments::None if segment.ident == "String" => Some((parse_quote! { u8 }, true)),
+        PathArguments::AngleBracketed(args) if segment.ident == "Vec" => {
+            match args.args.iter().collect::<Vec<_>>()[..] {
+                [GenericArgument::Type(elem)] => Some((elem.clone(), false)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Generates checks that the elements of each tail are `Copy`, since they are
+/// copied in and out of their record and never dropped
+pub fn tail_checks(tails: &[Option<Tail>]) -> TokenStream {
+    tails
+        .iter()
+        .flatten()
+        .map(|Tail { elem, .. }| {
+            quote_spanned! { elem.span() =>
+                const _: fn() = || {
+                    fn tail_is_copy<E: ::std::marker::Copy>() {}
+                    tail_is_copy::<#elem>();
+                };
+            }
+        })
+        .collect()
+}
+
+/// Generates the layout of the records of each variant with a tail, as a
+/// constant of its variant struct
+pub fn record_defs(e: &DataEnum, tails: &[Option<Tail>]) -> TokenStream {
+    e.variants
+        .iter()
+        .zip(tails)
+        .filter_map(|(variant, tail)| Some((variant, tail.as_ref()?)))
+        .map(|(variant, tail)| {
+            let ident = &variant.ident;
+            let elem = &tail.elem;
+            let heads: Vec<_> = variant.fields.iter().take(tail.index).collect();
+            let tys = heads.iter().map(|field| &field.ty);
+            // The struct is repr(C), so its tail comes right after the last
+            // other field
+            let end = match heads.last() {
+                Some(last) => {
+                    let field = IdentOrIndex::from_ident_index(&last.ident, tail.index - 1);
+                    let ty = &last.ty;
+                    quote! {
+                        ::std::mem::offset_of!(#ident, #field) + ::std::mem::size_of::<#ty>()
+                    }
+                }
+                None => quote! { 0 },
+            };
+            quote! {
+                impl #ident {
+                    /// How the records of the variant are laid out
+                    pub(super) const RECORD: ::packed_enum::TailLayout = {
+                        let aligns = [
+                            #( ::std::mem::align_of::<#tys>(), )*
+                            ::std::mem::align_of::<#elem>(),
+                        ];
+                        let mut align = 1;
+                        let mut i = 0;
+                        while i < aligns.len() {
+                            if aligns[i] > align {
+                                align = aligns[i];
+                            }
+                            i += 1;
+                        }
+                        let end: usize = #end;
+                        ::packed_enum::TailLayout::new(
+                            align,
+                            end.next_multiple_of(::std::mem::align_of::<#elem>()),
+                            ::std::mem::size_of::<#elem>(),
+                        )
+                    };
+                }
+            }
+        })
+        .collect()
+}
+
+/// Generates `Variant::tail_layout`, or nothing if no variant has a tail
+pub fn tail_layout(e: &DataEnum, tails: &[Option<Tail>]) -> TokenStream {
+    if tails.iter().all(Option::is_none) {
+        return quote! {};
+    }
+    let arms = e.variants.iter().zip(tails).map(|(variant, tail)| {
+        let ident = &variant.ident;
+        match tail {
+            Some(_) => quote! { Self::#ident => ::std::option::Option::Some(#ident::RECORD) },
+            None => quote! { Self::#ident => ::std::option::Option::None },
+        }
+    });
+    quote! {
+        fn tail_layout(&self) -> ::std::option::Option<::packed_enum::TailLayout> {
+            match self {
+                #( #arms, )*
+            }
+        }
+    }
+}
+
+/// Gets a pointer to the unsized variant struct in the record that the slot
+/// at `slot` points to
+fn head_ptr(module: &Ident, ident: &Ident, slot: TokenStream) -> TokenStream {
+    quote! {
+        (unsafe { #module::#ident::RECORD.head_ptr(#slot) } as *mut #module::#ident)
+    }
+}
+
+/// Wraps the reads of a variant with a tail so that `data` points to its
+/// variant struct rather than its slot, with `strukt` pointing to the whole
+/// unsized struct, returning the owned, shared and mutable reads
+pub fn record_reads(
+    module: &Ident,
+    ident: &Ident,
+    tail: Option<&Tail>,
+    (read_own, read_ref, read_mut): (TokenStream, TokenStream, TokenStream),
+) -> (TokenStream, TokenStream, TokenStream) {
+    if tail.is_none() {
+        return (read_own, read_ref, read_mut);
+    }
+    let strukt = head_ptr(module, ident, quote! { data });
+    let wrap = |read| {
+        quote! {
+            let strukt = #strukt;
+            let data = strukt.cast::<u8>();
+            #read
+        }
+    };
+    (wrap(read_own), wrap(read_ref), wrap(read_mut))
+}
+
+/// Generates the owned, shared and mutable reads of the tail of the variant
+/// struct at `strukt`
+pub fn tail_reads(tail: &Tail, field: &IdentOrIndex) -> (TokenStream, TokenStream, TokenStream) {
+    let elems = quote! { unsafe { &(*strukt).#field } };
+    let own = match tail.string {
+        true => quote! { ::std::string::String::from(#elems) },
+        false => quote! { #elems.to_vec() },
+    };
+    (
+        quote! { #field: #own },
+        quote! { #field: #elems },
+        quote! { #field: unsafe { &mut (*strukt).#field } },
+    )
+}
+
+/// Generates moving the fields bound in the match arm of `Packable::write`
+/// into the record the slot at `dst` points to
+pub fn write_record(module: &Ident, variant: &Variant, tail: &Tail) -> TokenStream {
+    let ident = &variant.ident;
+    let variables = field_variables(&variant.fields);
+    let fields: Vec<_> = variant
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| IdentOrIndex::from_ident_index(&field.ident, i))
+        .collect();
+    let heads = &variables[..tail.index];
+    let head_fields = &fields[..tail.index];
+    let tail_variable = &variables[tail.index];
+    let tail_field = &fields[tail.index];
+    let elem = &tail.elem;
+    let strukt = head_ptr(module, ident, quote! { dst });
+    quote! {
+        let tail = unsafe { ::std::ptr::from_ref(#tail_variable).read() };
+        let elems: &[#elem] = ::std::convert::AsRef::as_ref(&tail);
+        unsafe {
+            let record = dst.cast::<*mut u8>().read();
+            record.cast::<usize>().write(elems.len());
+        }
+        let strukt = #strukt;
+        #(
+        unsafe {
+            ::std::ptr::addr_of_mut!((*strukt).#head_fields)
+                .write(::std::ptr::from_ref(#heads).read());
+        }
+        )*
+        unsafe {
+            ::std::ptr::addr_of_mut!((*strukt).#tail_field)
+                .cast::<#elem>()
+                .copy_from_nonoverlapping(elems.as_ptr(), elems.len());
+        }
+        // The elements are Copy, so only the buffer is left to free
+        ::std::mem::drop(tail);
+    }
+}
+
+/// Generates dropping the variant structs in the records that `len` slots
+/// starting at `data` point to. The elements of the tails need no drop.
+pub fn drop_records(module: &Ident, ident: &Ident) -> TokenStream {
+    let strukt = head_ptr(module, ident, quote! { slot });
+    quote! {
+        for i in 0..len {
+            let slot = unsafe { data.cast::<*mut u8>().add(i) }.cast::<u8>();
+            unsafe { ::std::ptr::drop_in_place(#strukt) };
+        }
+    }
+}
+
+/// Generates `Packable::tail_len` and `Packable::forget_copy`
+pub fn tail_methods(enom: &Ident, e: &DataEnum, tails: &[Option<Tail>]) -> TokenStream {
+    let patterns: Vec<_> = e
+        .variants
+        .iter()
+        .zip(tails)
+        .filter_map(|(variant, tail)| Some(tail.as_ref()?.pattern(enom, variant)))
+        .collect();
+    quote! {
+        #[allow(unreachable_patterns)]
+        fn tail_len(&self) -> usize {
+            match self {
+                #( #patterns => ::std::convert::AsRef::<[_]>::as_ref(tail).len(), )*
+                _ => 0,
+            }
+        }
+
+        #[allow(unreachable_patterns)]
+        unsafe fn forget_copy(self) {
+            let me = ::std::mem::ManuallyDrop::new(self);
+            let me = <::std::mem::ManuallyDrop<Self> as ::std::ops::Deref>::deref(&me);
+            match me {
+                #(
+                // Only the tail was allocated by the read
+                #patterns => ::std::mem::drop(unsafe { ::std::ptr::from_ref(tail).read() }),
+                )*
+                _ => {}
+            }
+        }
+    }
+}
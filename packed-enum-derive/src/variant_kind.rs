@@ -12,7 +12,7 @@ impl ToTokens for VariantKind {
         match self {
             VariantKind::Empty => {}
             VariantKind::Tuple(field_count) => {
-                let iter = std::iter::repeat(quote! { _ }).take(*field_count);
+                let iter = std::iter::repeat_n(quote! { _ }, *field_count);
                 quote! { (#(#iter),*) }.to_tokens(tokens)
             }
             VariantKind::Struct => quote! { { .. } }.to_tokens(tokens),
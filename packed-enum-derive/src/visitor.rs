@@ -0,0 +1,104 @@
+use crate::{method_ident, tail::Tail};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{DataEnum, Visibility};
+
+/// Generates visitor traits with a defaulted method per variant, along with
+/// their dispatch from `Pack`. Every method receives the variant struct, which
+/// is unsized for variants with a tail.
+pub fn visitor(
+    vis: &Visibility,
+    enom: &Ident,
+    module: &Ident,
+    e: &DataEnum,
+    tails: &[Option<Tail>],
+) -> TokenStream {
+    let variant_idents: Vec<_> = e.variants.iter().map(|variant| &variant.ident).collect();
+    let methods: Vec<_> = variant_idents
+        .iter()
+        .map(|ident| format_ident!("visit_{}", method_ident(ident)))
+        .collect();
+    let method_docs: Vec<_> = variant_idents
+        .iter()
+        .map(|ident| format!("Visits a `{enom}::{ident}` at the given position"))
+        .collect();
+    // Variants with a tail point to their record rather than their struct
+    let (structs, structs_mut): (Vec<_>, Vec<_>) = variant_idents
+        .iter()
+        .zip(tails)
+        .map(|(ident, tail)| match tail {
+            Some(_) => (
+                quote! { &*(#module::#ident::RECORD.head_ptr(data) as *const #module::#ident) },
+                quote! { &mut *(#module::#ident::RECORD.head_ptr(data) as *mut #module::#ident) },
+            ),
+            None => (quote! { &*data.cast() }, quote! { &mut *data.cast() }),
+        })
+        .unzip();
+    let visitor = format_ident!("{}Visitor", enom);
+    let visitor_mut = format_ident!("{}VisitorMut", enom);
+    let visitor_doc = format!(
+        "Visits the elements of a `Pack<{enom}>` in order. Variants without an overridden method are skipped. See `Pack::visit`."
+    );
+    let visitor_mut_doc =
+        format!("Mutably visits the elements of a `Pack<{enom}>` in order. See `Pack::visit_mut`.");
+
+    quote! {
+        #[doc = #visitor_doc]
+        #[allow(dead_code)]
+        #vis trait #visitor {
+            #(
+            #[doc = #method_docs]
+            #[allow(unused_variables)]
+            fn #methods(&mut self, index: usize, v: &#module::#variant_idents) {}
+            )*
+        }
+
+        #[doc = #visitor_mut_doc]
+        #[allow(dead_code)]
+        #vis trait #visitor_mut {
+            #(
+            #[doc = #method_docs]
+            #[allow(unused_variables)]
+            fn #methods(&mut self, index: usize, v: &mut #module::#variant_idents) {}
+            )*
+        }
+
+        #[automatically_derived]
+        impl<V: #visitor + ?Sized> ::packed_enum::DispatchVisit<V> for #enom {
+            unsafe fn dispatch_visit(
+                visitor: &mut V,
+                index: usize,
+                variant: Self::Variant,
+                data: *const u8,
+            ) {
+                match variant {
+                    #(
+                    #module::Variant::#variant_idents => {
+                        let v = unsafe { #structs };
+                        visitor.#methods(index, v);
+                    }
+                    )*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl<V: #visitor_mut + ?Sized> ::packed_enum::DispatchVisitMut<V> for #enom {
+            unsafe fn dispatch_visit_mut(
+                visitor: &mut V,
+                index: usize,
+                variant: Self::Variant,
+                data: *mut u8,
+            ) {
+                match variant {
+                    #(
+                    #module::Variant::#variant_idents => {
+                        let v = unsafe { #structs_mut };
+                        visitor.#methods(index, v);
+                    }
+                    )*
+                }
+            }
+        }
+    }
+}
@@ -9,10 +9,104 @@ enum Test {
     C,
 }
 
+#[derive(Packable, Debug, Clone, PartialEq, Eq)]
+enum Owned {
+    Name(String),
+    Id(u32),
+}
+
+use std::{cell::Cell, rc::Rc};
+
+#[derive(Packable)]
+enum Counted {
+    Shared(Rc<()>),
+    Plain(u64),
+}
+
+#[derive(Packable)]
+enum Noisy {
+    Loud(Rc<Cell<u32>>),
+    Quiet,
+}
+
+impl Drop for Noisy {
+    fn drop(&mut self) {
+        if let Noisy::Loud(drops) = self {
+            drops.set(drops.get() + 1);
+        }
+    }
+}
+
+#[derive(Packable, Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Measure {
+    Length(f32),
+    Count(u32),
+}
+
+#[derive(Packable, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Lowered {
+    Word(u32),
+    Pair(u16, u16),
+    Nothing,
+}
+
+#[derive(Packable, Debug, Clone, PartialEq)]
+#[packed(share_buckets)]
+enum Mixed {
+    Id(u64),
+    Shared(Rc<()>),
+    Word(u32),
+    Float(f32),
+    Empty,
+}
+
+#[derive(Packable, Debug, Clone, PartialEq)]
+#[packed(inline)]
+enum Token {
+    Comma,
+    Number(u32),
+    Pair(u8, u8),
+    Ident(String),
+    Shared(Rc<()>),
+}
+
+#[derive(Packable, Debug, Clone, PartialEq)]
+#[packed(box_above = 64)]
+#[allow(clippy::large_enum_variant)]
+enum Large {
+    Table([u8; 256]),
+    #[packed(boxed)]
+    Shared(Rc<()>),
+    Name(String),
+    Small(u8),
+}
+
+#[derive(Packable, Debug, Clone, Copy, PartialEq)]
+#[packed(box_above = 64)]
+enum Blob {
+    Bytes([u8; 128]),
+    Byte(u8),
+}
+
+#[derive(Packable, Debug, Clone, PartialEq)]
+#[packed(share_buckets)]
+enum Lexeme {
+    Ident(#[packed(tail)] String),
+    Number(u64),
+    Literal {
+        owner: Rc<()>,
+        #[packed(tail)]
+        text: String,
+    },
+    Codes(u8, #[packed(tail)] Vec<u32>),
+    Comma,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use packed_enum::Pack;
+    use packed_enum::{Pack, PackBag, Runs, TagPack, VariantMap, VariantSet};
+    use test_types::{Mut as TestMut, Ref as TestRef, Variant as TestVariant};
 
     #[test]
     fn packed() {
@@ -25,4 +119,1533 @@ mod tests {
             assert_eq!(Some(expected), packed.pop());
         }
     }
+
+    #[test]
+    fn unordered() {
+        let pushed = [
+            Test::C,
+            Test::A(1, 2, 3, 4),
+            Test::B { foo: 5, bar: 6 },
+            Test::A(7, 8, 9, 10),
+            Test::C,
+        ];
+        let expected = [
+            Test::A(1, 2, 3, 4),
+            Test::A(7, 8, 9, 10),
+            Test::B { foo: 5, bar: 6 },
+            Test::C,
+            Test::C,
+        ];
+        let mut packed = Pack::new();
+        for el in pushed {
+            packed.push(el);
+        }
+
+        assert_eq!(packed.iter_unordered().count(), expected.len());
+        for el in packed.iter_unordered_mut() {
+            if let TestMut::A(a, ..) = el {
+                *a += 10;
+            }
+        }
+        let sum: u8 = packed
+            .iter_unordered()
+            .map(|el| match el {
+                TestRef::A(a, ..) => *a,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(sum, 28);
+
+        let actual: Vec<_> = packed.into_iter_unordered().collect();
+        let expected = expected.map(|el| match el {
+            Test::A(a, b, c, d) => Test::A(a + 10, b, c, d),
+            el => el,
+        });
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn map() {
+        let pushed = [
+            Test::B { foo: 1, bar: 2 },
+            Test::A(1, 2, 3, 4),
+            Test::C,
+            Test::A(5, 0, 0, 0),
+            Test::B { foo: 3, bar: 4 },
+        ];
+        let mut packed = Pack::new();
+        for el in pushed {
+            packed.push(el);
+        }
+
+        let mut mapped = packed.map_ref(|el| match el {
+            TestRef::A(a, ..) if *a > 1 => Lowered::Nothing,
+            TestRef::A(a, b, c, d) => Lowered::Word(u32::from_le_bytes([*a, *b, *c, *d])),
+            TestRef::B { foo, bar } => Lowered::Pair(*foo, *bar),
+            TestRef::C => Lowered::Nothing,
+        });
+        let expected = [
+            Lowered::Pair(1, 2),
+            Lowered::Word(0x04030201),
+            Lowered::Nothing,
+            Lowered::Nothing,
+            Lowered::Pair(3, 4),
+        ];
+        for expected in expected.into_iter().rev() {
+            assert_eq!(Some(expected), mapped.pop());
+        }
+
+        // The closure sees the elements in insertion order
+        let mut next = 0;
+        let numbered = packed.map_ref(|_| {
+            next += 1;
+            Lowered::Word(next)
+        });
+        let mut next = 0;
+        let renumbered = packed.clone().map(|_| {
+            next += 1;
+            Lowered::Word(next)
+        });
+        let expected: Vec<_> = (1..=5).map(Lowered::Word).collect();
+        assert_eq!(numbered, expected);
+        assert_eq!(renumbered, expected);
+
+        let mut mapped = packed.map(|el| match el {
+            Test::A(a, ..) => Lowered::Word(a.into()),
+            Test::B { foo, bar } => Lowered::Pair(bar, foo),
+            Test::C => Lowered::Nothing,
+        });
+        let expected = [
+            Lowered::Pair(2, 1),
+            Lowered::Word(1),
+            Lowered::Nothing,
+            Lowered::Word(5),
+            Lowered::Pair(4, 3),
+        ];
+        for expected in expected.into_iter().rev() {
+            assert_eq!(Some(expected), mapped.pop());
+        }
+    }
+
+    #[test]
+    fn map_panic() {
+        let rc = Rc::new(());
+        let packed: Pack<_> = (0..6)
+            .map(|i| match i % 2 {
+                0 => Counted::Shared(rc.clone()),
+                _ => Counted::Plain(i),
+            })
+            .collect();
+
+        // Both variants of Counted map into one bucket of Mixed, which can
+        // only be dropped through the entries of the mapped collection
+        let mut mapped = 0;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            packed.map(|element| {
+                mapped += 1;
+                assert!(mapped < 5, "mapping failed");
+                match element {
+                    Counted::Shared(rc) => Mixed::Shared(rc),
+                    Counted::Plain(id) => Mixed::Id(id),
+                }
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        // Elements left in a shared bucket of the source are dropped through
+        // their entries
+        let mixed = || -> Pack<_> {
+            (0..12)
+                .map(|i| match i % 3 {
+                    0 => Mixed::Shared(rc.clone()),
+                    1 => Mixed::Id(i),
+                    _ => Mixed::Word(i as u32),
+                })
+                .collect()
+        };
+        for fail_at in [3, 6, 10] {
+            let packed = mixed();
+            let mut mapped = 0;
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                packed.map(|element| {
+                    mapped += 1;
+                    assert!(mapped < fail_at, "mapping failed");
+                    match element {
+                        Mixed::Shared(rc) => Counted::Shared(rc),
+                        _ => Counted::Plain(0),
+                    }
+                })
+            }));
+            assert!(result.is_err());
+            assert_eq!(Rc::strong_count(&rc), 1);
+
+            let packed = mixed();
+            let mut mapped = 0;
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                packed.map(|element| {
+                    mapped += 1;
+                    assert!(mapped < fail_at, "mapping failed");
+                    match element {
+                        Mixed::Id(id) => Mixed::Word(id as u32),
+                        element => element,
+                    }
+                })
+            }));
+            assert!(result.is_err());
+            assert_eq!(Rc::strong_count(&rc), 1);
+        }
+
+        let mapped = mixed().map(|element| match element {
+            Mixed::Word(word) => Mixed::Id(word.into()),
+            element => element,
+        });
+        assert_eq!(Rc::strong_count(&rc), 5);
+        assert_eq!(mapped.count_id(), 8);
+        drop(mapped);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn rank() {
+        let mut packed = Pack::new();
+        for el in [
+            Test::C,
+            Test::A(1, 2, 3, 4),
+            Test::C,
+            Test::C,
+            Test::A(0, 0, 0, 0),
+        ] {
+            packed.push(el);
+        }
+
+        let ranks = packed.ranks();
+        assert_eq!(ranks.nth_of_variant(TestVariant::C, 2), Some(3));
+        assert_eq!(ranks.nth_of_variant(TestVariant::A, 1), Some(4));
+        assert_eq!(ranks.nth_of_variant(TestVariant::B, 0), None);
+        assert_eq!(ranks.rank(TestVariant::C, 3), 2);
+        assert_eq!(ranks.rank(TestVariant::A, 5), 2);
+        assert_eq!(ranks.position_of(TestVariant::A, 0), Some(1));
+
+        packed.pop();
+        packed.push(Test::B { foo: 0, bar: 0 });
+        let ranks = packed.ranks();
+        assert_eq!(ranks.nth_of_variant(TestVariant::A, 1), None);
+        assert_eq!(ranks.position_of(TestVariant::B, 0), Some(4));
+
+        // Span several blocks of the directory
+        let mut packed: Pack<Test, Runs> = Pack::default();
+        let mut variants = vec![];
+        for i in 0..1000u32 {
+            let element = match i % 7 {
+                0 | 3 => Test::A(0, 0, 0, 0),
+                1 if i < 500 => Test::B { foo: 0, bar: 0 },
+                _ => Test::C,
+            };
+            variants.push(element.variant());
+            packed.push(element);
+        }
+        for _ in 0..2 {
+            let ranks = packed.ranks();
+            for variant in [TestVariant::A, TestVariant::B, TestVariant::C] {
+                let positions: Vec<_> = (0..variants.len())
+                    .filter(|&i| variants[i] == variant)
+                    .collect();
+                for (k, &position) in positions.iter().enumerate() {
+                    assert_eq!(ranks.nth_of_variant(variant, k), Some(position));
+                    assert_eq!(ranks.rank(variant, position), k);
+                }
+                assert_eq!(ranks.nth_of_variant(variant, positions.len()), None);
+                assert_eq!(ranks.rank(variant, variants.len()), positions.len());
+            }
+            packed.clear_variants(&VariantSet::single(TestVariant::A));
+            variants.retain(|&variant| variant != TestVariant::A);
+        }
+    }
+
+    #[test]
+    fn handles() {
+        let mut packed = Pack::new();
+        packed.push(Test::C);
+        let a = packed.push_handle(Test::A(1, 2, 3, 4));
+        let b = packed.push_handle(Test::B { foo: 5, bar: 6 });
+        packed.push(Test::C);
+        packed.pop();
+        packed.push(Test::A(0, 0, 0, 0));
+
+        assert!(matches!(
+            packed.get_by_handle(a),
+            Some(TestRef::A(1, 2, 3, 4))
+        ));
+        if let Some(TestMut::B { foo, .. }) = packed.get_mut_by_handle(b) {
+            *foo = 7;
+        }
+        assert!(matches!(
+            packed.get_by_handle(b),
+            Some(TestRef::B { foo: 7, bar: 6 })
+        ));
+
+        packed.pop();
+        packed.pop();
+        packed.push(Test::B { foo: 8, bar: 9 });
+        assert!(packed.get_by_handle(b).is_none());
+        assert!(packed.get_by_handle(a).is_some());
+    }
+
+    #[test]
+    fn history_bounded() {
+        // Growing by two and shrinking by one skips a stamp each time, which
+        // starts a new run once a handle is made
+        let mut packed = Pack::new();
+        for _ in 0..1000 {
+            packed.push(Test::C);
+            packed.push(Test::C);
+            packed.pop();
+        }
+        assert_eq!(packed.history_len(), 0);
+
+        let handle = packed.push_handle(Test::C);
+        for _ in 0..10 {
+            packed.push(Test::C);
+            packed.push(Test::C);
+            packed.pop();
+        }
+        assert_eq!(packed.history_len(), 10);
+        packed.clear_variants(&VariantSet::single(TestVariant::C));
+        assert_eq!(packed.history_len(), 0);
+        assert!(packed.get_by_handle(handle).is_none());
+    }
+
+    #[test]
+    fn handles_after_removal() {
+        let mut packed = Pack::new();
+        let a = packed.push_handle(Test::A(1, 2, 3, 4));
+        packed.push(Test::C);
+        let b = packed.push_handle(Test::B { foo: 5, bar: 6 });
+        let c = packed.push_handle(Test::C);
+        packed.push(Test::A(7, 8, 9, 10));
+        let last = packed.push_handle(Test::B { foo: 11, bar: 12 });
+
+        packed.retain_variants(&[TestVariant::A, TestVariant::B].into_iter().collect());
+        assert_eq!(packed.position_by_handle(a), Some(0));
+        assert_eq!(packed.position_by_handle(b), Some(1));
+        assert_eq!(packed.position_by_handle(last), Some(3));
+        assert!(packed.get_by_handle(c).is_none());
+
+        packed.compact();
+        packed.pop();
+        packed.push(Test::C);
+        assert!(packed.get_by_handle(last).is_none());
+        assert!(matches!(
+            packed.get_by_handle(b),
+            Some(TestRef::B { foo: 5, bar: 6 })
+        ));
+
+        // Elements of shared buckets are moved down over the removed ones
+        let mut mixed = Pack::new();
+        mixed.push(Mixed::Word(1));
+        let id = mixed.push_handle(Mixed::Id(2));
+        mixed.push(Mixed::Word(3));
+        let float = mixed.push_handle(Mixed::Float(4.0));
+        mixed.clear_variants(&VariantSet::single(mixed_types::Variant::Word));
+        mixed.compact();
+        if let Some(mixed_types::Mut::Id(id)) = mixed.get_mut_by_handle(id) {
+            *id = 5;
+        }
+        assert_eq!(mixed.position_by_handle(float), Some(1));
+        assert_eq!(mixed, [Mixed::Id(5), Mixed::Float(4.0)]);
+
+        // Removing elements pushed after a checkpoint doesn't stop it from
+        // rolling back
+        let checkpoint = mixed.checkpoint();
+        mixed.push(Mixed::Word(6));
+        mixed.push(Mixed::Empty);
+        mixed.clear_variants(&VariantSet::single(mixed_types::Variant::Word));
+        mixed.rollback_to(checkpoint);
+        assert_eq!(mixed, [Mixed::Id(5), Mixed::Float(4.0)]);
+    }
+
+    #[test]
+    fn emplace() {
+        let mut packed = Pack::new();
+        if let TestMut::A(a, ..) = packed.push(Test::A(1, 2, 3, 4)) {
+            *a = 9;
+        }
+        packed.push_with(|slot| slot.write(test_types::B { foo: 5, bar: 6 }));
+        packed.push_with(|slot: &mut std::mem::MaybeUninit<test_types::A>| {
+            let ptr = slot.as_mut_ptr();
+            unsafe {
+                std::ptr::addr_of_mut!((*ptr).0).write(10);
+                std::ptr::addr_of_mut!((*ptr).1).write(11);
+                std::ptr::addr_of_mut!((*ptr).2).write(12);
+                std::ptr::addr_of_mut!((*ptr).3).write(13);
+                slot.assume_init_mut()
+            }
+        });
+
+        let expected = [
+            Test::A(9, 2, 3, 4),
+            Test::B { foo: 5, bar: 6 },
+            Test::A(10, 11, 12, 13),
+        ];
+        for expected in expected.into_iter().rev() {
+            assert_eq!(Some(expected), packed.pop());
+        }
+    }
+
+    #[test]
+    fn rollback() {
+        let mut packed = Pack::new();
+        packed.push(Test::A(1, 2, 3, 4));
+        packed.push(Test::C);
+        let checkpoint = packed.checkpoint();
+        packed.push(Test::B { foo: 5, bar: 6 });
+        packed.push(Test::A(7, 8, 9, 10));
+        packed.pop();
+        packed.push(Test::C);
+        packed.rollback_to(checkpoint);
+
+        assert_eq!(packed.len(), 2);
+        assert_eq!(packed.iter_unordered().count(), 2);
+        packed.push(Test::A(0, 0, 0, 0));
+        let expected = [Test::A(1, 2, 3, 4), Test::C, Test::A(0, 0, 0, 0)];
+        for expected in expected.into_iter().rev() {
+            assert_eq!(Some(expected), packed.pop());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rollback_after_removal() {
+        let mut packed = Pack::new();
+        packed.push(Test::C);
+        let checkpoint = packed.checkpoint();
+        packed.pop();
+        packed.push(Test::C);
+        packed.rollback_to(checkpoint);
+    }
+
+    #[test]
+    fn std_traits() {
+        use std::collections::HashSet;
+
+        let elements = vec![Test::A(1, 2, 3, 4), Test::B { foo: 5, bar: 6 }, Test::C];
+        let mut packed = Pack::new();
+        for el in elements.iter().copied() {
+            packed.push(el);
+        }
+
+        assert_eq!(format!("{packed:?}"), format!("{elements:?}"));
+        assert_eq!(packed, elements);
+        assert_eq!(packed, elements[..]);
+
+        let mut other = Pack::new();
+        other.push(Test::A(1, 2, 3, 4));
+        other.push(Test::B { foo: 5, bar: 7 });
+        assert_ne!(packed, other);
+        assert!(packed < other);
+        other.pop();
+        assert!(other < packed);
+        other.push(Test::C);
+        assert!(packed < other);
+
+        let set: HashSet<_> = [packed, other].into_iter().collect();
+        assert_eq!(set.len(), 2);
+
+        let mut measures = Pack::new();
+        measures.push(Measure::Length(1.5));
+        measures.push(Measure::Count(2));
+        assert_eq!(measures, [Measure::Length(1.5), Measure::Count(2)]);
+        assert!(measures.partial_cmp(&measures).is_some());
+    }
+
+    #[test]
+    fn clone() {
+        let mut packed = Pack::new();
+        packed.push(Test::B { foo: 1, bar: 2 });
+        packed.push(Test::C);
+        packed.push(Test::A(3, 4, 5, 6));
+        let mut cloned = packed.clone();
+        assert_eq!(cloned, packed);
+        cloned.push(Test::C);
+        assert_ne!(cloned, packed);
+
+        let mut owned = Pack::new();
+        owned.push(Owned::Name("first".to_string()));
+        owned.push(Owned::Id(1));
+        owned.push(Owned::Name("second".to_string()));
+        let cloned = owned.clone();
+        drop(owned);
+        assert_eq!(
+            cloned,
+            [
+                Owned::Name("first".to_string()),
+                Owned::Id(1),
+                Owned::Name("second".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn drop_buckets() {
+        let shared = Rc::new(());
+        let mut packed = Pack::new();
+        for i in 0..10 {
+            packed.push(Counted::Shared(shared.clone()));
+            packed.push(Counted::Plain(i));
+        }
+        let checkpoint = packed.checkpoint();
+        packed.push(Counted::Shared(shared.clone()));
+        assert_eq!(Rc::strong_count(&shared), 12);
+        packed.rollback_to(checkpoint);
+        assert_eq!(Rc::strong_count(&shared), 11);
+        std::mem::drop(packed);
+        assert_eq!(Rc::strong_count(&shared), 1);
+
+        let drops = Rc::new(Cell::new(0));
+        let mut packed = Pack::new();
+        packed.push(Noisy::Loud(drops.clone()));
+        packed.push(Noisy::Quiet);
+        packed.push(Noisy::Loud(drops.clone()));
+        std::mem::drop(packed);
+        assert_eq!(drops.get(), 2);
+        assert_eq!(Rc::strong_count(&drops), 1);
+    }
+
+    #[test]
+    fn batch() {
+        #[derive(Default)]
+        struct Step {
+            calls: usize,
+            total: u32,
+        }
+
+        impl TestBatch for Step {
+            fn a(&mut self, items: &mut [test_types::A]) {
+                self.calls += 1;
+                for item in items {
+                    item.0 += 1;
+                    self.total += u32::from(item.0);
+                }
+            }
+
+            fn b(&mut self, items: &mut [test_types::B]) {
+                self.calls += 1;
+                self.total += items.iter().map(|item| u32::from(item.foo)).sum::<u32>();
+            }
+
+            fn c(&mut self, items: &mut [test_types::C]) {
+                self.calls += 1;
+                self.total += items.len() as u32;
+            }
+        }
+
+        let mut packed = Pack::new();
+        packed.push(Test::A(1, 0, 0, 0));
+        packed.push(Test::C);
+        packed.push(Test::A(2, 0, 0, 0));
+        packed.push(Test::C);
+
+        let mut step = Step::default();
+        packed.for_each_batch(&mut step);
+        assert_eq!(step.calls, 3);
+        assert_eq!(step.total, 2 + 3 + 2);
+        assert_eq!(
+            packed,
+            [Test::A(2, 0, 0, 0), Test::C, Test::A(3, 0, 0, 0), Test::C]
+        );
+    }
+
+    #[test]
+    fn visit() {
+        #[derive(Default)]
+        struct Positions(Vec<usize>);
+
+        impl TestVisitor for Positions {
+            fn visit_c(&mut self, index: usize, _: &test_types::C) {
+                self.0.push(index);
+            }
+        }
+
+        struct Bump;
+
+        impl TestVisitorMut for Bump {
+            fn visit_b(&mut self, _: usize, v: &mut test_types::B) {
+                v.bar += 1;
+            }
+        }
+
+        let mut packed = Pack::new();
+        packed.push(Test::C);
+        packed.push(Test::B { foo: 1, bar: 2 });
+        packed.push(Test::A(1, 2, 3, 4));
+        packed.push(Test::C);
+
+        let mut positions = Positions::default();
+        packed.visit(&mut positions);
+        assert_eq!(positions.0, [0, 3]);
+
+        packed.visit_mut(&mut Bump);
+        assert!(matches!(packed.get(1), Some(TestRef::B { foo: 1, bar: 3 })));
+
+        // Variants with a tail are visited as their unsized variant struct
+        #[derive(Default)]
+        struct Lengths(Vec<(usize, u8, usize)>);
+
+        impl LexemeVisitor for Lengths {
+            fn visit_ident(&mut self, index: usize, v: &lexeme_types::Ident) {
+                self.0.push((index, 0, v.0.len()));
+            }
+
+            fn visit_codes(&mut self, index: usize, v: &lexeme_types::Codes) {
+                self.0.push((index, v.0, v.1.len()));
+            }
+
+            fn visit_literal(&mut self, index: usize, v: &lexeme_types::Literal) {
+                self.0
+                    .push((index, Rc::strong_count(&v.owner) as u8, v.text.len()));
+            }
+        }
+
+        struct Shift;
+
+        impl LexemeVisitorMut for Shift {
+            fn visit_ident(&mut self, _: usize, v: &mut lexeme_types::Ident) {
+                v.0.make_ascii_uppercase();
+            }
+
+            fn visit_codes(&mut self, _: usize, v: &mut lexeme_types::Codes) {
+                v.0 += 1;
+                v.1.iter_mut().for_each(|code| *code *= 2);
+            }
+        }
+
+        let owner = Rc::new(());
+        let mut lexemes = Pack::new();
+        lexemes.push(Lexeme::Ident("ab".into()));
+        lexemes.push(Lexeme::Number(1));
+        lexemes.push(Lexeme::Codes(7, vec![1, 2, 3]));
+        lexemes.push(Lexeme::Literal {
+            owner: owner.clone(),
+            text: "text".into(),
+        });
+        lexemes.push(Lexeme::Comma);
+
+        let mut lengths = Lengths::default();
+        lexemes.visit(&mut lengths);
+        assert_eq!(lengths.0, [(0, 0, 2), (2, 7, 3), (3, 2, 4)]);
+
+        lexemes.visit_mut(&mut Shift);
+        assert_eq!(
+            lexemes,
+            [
+                Lexeme::Ident("AB".into()),
+                Lexeme::Number(1),
+                Lexeme::Codes(8, vec![2, 4, 6]),
+                Lexeme::Literal {
+                    owner,
+                    text: "text".into(),
+                },
+                Lexeme::Comma,
+            ]
+        );
+    }
+
+    #[test]
+    fn query() {
+        let packed: Pack<_> = [
+            Test::A(1, 0, 0, 0),
+            Test::B { foo: 2, bar: 0 },
+            Test::C,
+            Test::A(3, 0, 0, 0),
+            Test::B { foo: 4, bar: 1 },
+        ]
+        .into_iter()
+        .collect();
+
+        let a_or_b: VariantSet<Test> = [TestVariant::A, TestVariant::B].into_iter().collect();
+        let query = || {
+            packed.query().only(&a_or_b).filter(|el| match el {
+                TestRef::A(a, ..) => **a > 1,
+                TestRef::B { bar, .. } => **bar > 0,
+                TestRef::C => true,
+            })
+        };
+        assert_eq!(query().count(), 2);
+        assert_eq!(query().positions().collect::<Vec<_>>(), [3, 4]);
+        let subset = query().to_pack();
+        assert_eq!(subset, [Test::A(3, 0, 0, 0), Test::B { foo: 4, bar: 1 }]);
+        let owned: Pack<_> = [
+            Owned::Name("a".into()),
+            Owned::Id(1),
+            Owned::Name("b".into()),
+        ]
+        .into_iter()
+        .collect();
+        let names = owned
+            .query()
+            .only(&VariantSet::single(owned_types::Variant::Name))
+            .to_pack();
+        drop(owned);
+        assert_eq!(names, [Owned::Name("a".into()), Owned::Name("b".into())]);
+        assert_eq!(packed.query().count(), 5);
+
+        let only_c = [TestVariant::C].into_iter().collect();
+        assert_eq!(
+            packed.query().only(&only_c).positions().collect::<Vec<_>>(),
+            [2]
+        );
+
+        let lowered: Pack<Lowered> = query()
+            .map(|el| match el {
+                TestRef::A(a, ..) => Lowered::Word((*a).into()),
+                TestRef::B { foo, bar } => Lowered::Pair(*foo, *bar),
+                TestRef::C => Lowered::Nothing,
+            })
+            .collect();
+        assert_eq!(lowered, [Lowered::Word(3), Lowered::Pair(4, 1)]);
+
+        let sum = packed.query().fold(0, |acc, el| match el {
+            TestRef::A(a, ..) => acc + u32::from(*a),
+            _ => acc,
+        });
+        assert_eq!(sum, 4);
+    }
+
+    #[test]
+    fn columns() {
+        let mut packed: Pack<_> = [
+            Test::B { foo: 1, bar: 10 },
+            Test::A(1, 2, 3, 4),
+            Test::B { foo: 2, bar: 20 },
+            Test::C,
+        ]
+        .into_iter()
+        .collect();
+
+        let foos: Vec<_> = packed.column::<test_types::B>().foo().copied().collect();
+        assert_eq!(foos, [1, 2]);
+        assert_eq!(packed.column::<test_types::B>().bar().sum::<u16>(), 30);
+        assert_eq!(packed.bucket::<test_types::C>().len(), 1);
+
+        for a in packed.column_mut::<test_types::A>()._3() {
+            *a *= 2;
+        }
+        assert_eq!(packed.bucket::<test_types::A>()[0].3, 8);
+    }
+
+    #[test]
+    fn variant_map() {
+        let mut capacities = VariantMap::<Test, usize>::default();
+        capacities[TestVariant::A] = 2;
+        capacities[TestVariant::C] = 8;
+        let mut packed = Pack::with_variant_capacities(capacities.clone());
+        assert!(packed.capacity() >= 10);
+
+        packed.extend([Test::A(1, 2, 3, 4), Test::C, Test::C]);
+        let counts = packed.counts();
+        assert_eq!(counts[TestVariant::A], 1);
+        assert_eq!(counts[TestVariant::B], 0);
+        assert_eq!(counts[TestVariant::C], 2);
+        assert_eq!(
+            counts.iter().collect::<Vec<_>>(),
+            [
+                (TestVariant::A, &1),
+                (TestVariant::B, &0),
+                (TestVariant::C, &2)
+            ]
+        );
+
+        let doubled = counts.map(|count| count * 2);
+        assert_eq!(doubled.values().sum::<usize>(), 6);
+        let names = VariantMap::<Test, _>::from_fn(|variant| format!("{variant:?}"));
+        assert_eq!(names[TestVariant::B], "B");
+    }
+
+    #[test]
+    fn variant_set() {
+        let a = VariantSet::<Test>::single(TestVariant::A);
+        let ab: VariantSet<Test> = [TestVariant::A, TestVariant::B].into_iter().collect();
+        let c = !&ab;
+        assert_eq!(c.iter().collect::<Vec<_>>(), [TestVariant::C]);
+        assert_eq!(&ab - &a, VariantSet::single(TestVariant::B));
+        assert_eq!((&a | &c).len(), 2);
+        assert!((&a & &c).is_empty());
+        assert_eq!(&ab ^ &a, VariantSet::single(TestVariant::B));
+        assert!(a.is_subset(&ab) && ab.is_superset(&a) && a.is_disjoint(&c));
+        let mut without_a = ab;
+        assert!(without_a.remove(TestVariant::A) && !without_a.remove(TestVariant::A));
+        assert_eq!(ab.len(), 2);
+
+        let mut packed: Pack<_> = [
+            Test::A(1, 2, 3, 4),
+            Test::C,
+            Test::B { foo: 5, bar: 6 },
+            Test::C,
+            Test::A(7, 8, 9, 10),
+        ]
+        .into_iter()
+        .collect();
+        let kept = packed.push_handle(Test::C);
+        assert_eq!(packed.count_in(&ab), 3);
+        assert_eq!(packed.iter_only(&c).count(), 3);
+
+        let mut cleared = packed.clone();
+        cleared.clear_variants(&ab);
+        assert_eq!(cleared, [Test::C, Test::C, Test::C]);
+
+        packed.retain_variants(&ab);
+        assert_eq!(
+            packed,
+            [
+                Test::A(1, 2, 3, 4),
+                Test::B { foo: 5, bar: 6 },
+                Test::A(7, 8, 9, 10)
+            ]
+        );
+        assert!(packed.get_by_handle(kept).is_none());
+    }
+
+    #[test]
+    fn pack_ext() {
+        let mut packed = Pack::<Test>::new();
+        packed.push_b(1, 10);
+        packed.push_a(1, 2, 3, 4);
+        packed.extend_b((2..5).map(|i| test_types::B {
+            foo: i,
+            bar: i * 10,
+        }));
+        packed.push_c();
+
+        assert_eq!(packed.count_b(), 4);
+        assert_eq!(packed.count_c(), 1);
+        assert_eq!(packed.iter_b().map(|b| b.bar).sum::<u16>(), 100);
+        assert_eq!(packed.last_b().map(|b| b.foo), Some(4));
+        assert!(packed.last_a().is_some_and(|a| a.3 == 4));
+        assert_eq!(
+            packed,
+            [
+                Test::B { foo: 1, bar: 10 },
+                Test::A(1, 2, 3, 4),
+                Test::B { foo: 2, bar: 20 },
+                Test::B { foo: 3, bar: 30 },
+                Test::B { foo: 4, bar: 40 },
+                Test::C,
+            ]
+        );
+        assert_eq!(packed.pop(), Some(Test::C));
+        assert_eq!(packed.pop(), Some(Test::B { foo: 4, bar: 40 }));
+
+        // Payloads in a Vec are moved into their bucket in one go
+        let a = |i: u8| test_types::A(i, 0, 0, 0);
+        let mut runs = Pack::<Test, Runs>::default();
+        let mut tags = TagPack::<Test>::default();
+        runs.push_c();
+        tags.push_c();
+        let mut payloads: Vec<_> = (0..100).map(a).collect();
+        runs.append_a(&mut payloads);
+        assert!(payloads.is_empty());
+        runs.extend_a((100..200).map(a));
+        tags.extend_a((0..200).map(a));
+        runs.extend_a([]);
+        runs.append_a(&mut payloads);
+        runs.push_a(200, 0, 0, 0);
+        tags.push_a(200, 0, 0, 0);
+        let expected: Vec<_> = [Test::C]
+            .into_iter()
+            .chain((0..=200).map(|i| Test::A(i, 0, 0, 0)))
+            .collect();
+        assert_eq!(runs, expected);
+        assert_eq!(tags, expected);
+        assert_eq!(runs.ranks().nth_of_variant(TestVariant::A, 200), Some(201));
+        runs.reserve_variants(VariantMap::from_fn(|_| 10));
+        assert!(runs.capacity() >= runs.len() + 30);
+        runs.compact();
+        assert_eq!(runs.capacity(), runs.len());
+        assert_eq!(runs.pop(), Some(Test::A(200, 0, 0, 0)));
+        assert_eq!(runs.pop(), Some(Test::A(199, 0, 0, 0)));
+
+        let mut owned = Pack::<Owned>::new();
+        owned.push_id(1);
+        let mut names: Vec<_> = ["a", "b"].map(|name| owned_types::Name(name.into())).into();
+        owned.append_name(&mut names);
+        assert!(names.is_empty());
+        assert_eq!(
+            owned,
+            [
+                Owned::Id(1),
+                Owned::Name("a".into()),
+                Owned::Name("b".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn index_width() {
+        let mut packed = Pack::<Test, u8>::default();
+        assert_eq!(Pack::<Test, u8>::max_capacity(), 256);
+        for i in 0..=255 {
+            packed.push(Test::A(i, 0, 0, 0));
+        }
+        packed.push(Test::C);
+        packed.push_b(1, 2);
+        assert_eq!(packed.len(), 258);
+        assert!(matches!(packed.get(200), Some(TestRef::A(200, ..))));
+
+        let mapped = packed.map_ref(|el| match el {
+            TestRef::A(a, ..) => Lowered::Word((*a).into()),
+            _ => Lowered::Nothing,
+        });
+        assert!(matches!(
+            mapped.get(255),
+            Some(lowered_types::Ref::Word(255))
+        ));
+    }
+
+    #[test]
+    #[should_panic = "Too many elements of one variant"]
+    fn index_overflow() {
+        let mut packed = Pack::<Test, u8>::default();
+        for _ in 0..=256 {
+            packed.push(Test::C);
+        }
+    }
+
+    #[test]
+    fn runs() {
+        let mut packed = Pack::<Test, Runs>::default();
+        assert_eq!(packed.capacity(), 0);
+        packed.reserve_variants(VariantMap::from_fn(|_| 10));
+        assert!(packed.capacity() >= 1);
+        packed.extend(std::iter::repeat_n(Test::C, 100));
+        packed.extend((0..50).map(|i| Test::A(i, 0, 0, 0)));
+        packed.push(Test::B { foo: 1, bar: 2 });
+        packed.extend((50..60).map(|i| Test::A(i, 0, 0, 0)));
+        packed.push(Test::C);
+        assert_eq!(packed.len(), 162);
+        assert!(matches!(packed.get(99), Some(TestRef::C)));
+        assert!(matches!(packed.get(149), Some(TestRef::A(49, ..))));
+        assert!(matches!(
+            packed.get(150),
+            Some(TestRef::B { foo: 1, bar: 2 })
+        ));
+        assert!(matches!(packed.get(151), Some(TestRef::A(50, ..))));
+        assert!(packed.get(162).is_none());
+
+        let expected: Vec<_> = packed.iter().map(|el| format!("{el:?}")).collect();
+        let flat: Pack<_> = packed.clone().into_iter_unordered().collect();
+        assert_eq!(flat.len(), 162);
+        assert_eq!(packed.pop(), Some(Test::C));
+        packed.clear_variants(&VariantSet::single(TestVariant::B));
+        assert_eq!(packed.len(), 160);
+        assert!(matches!(packed.get(150), Some(TestRef::A(50, ..))));
+        assert_eq!(
+            packed
+                .iter()
+                .map(|el| format!("{el:?}"))
+                .collect::<Vec<_>>(),
+            expected[..150]
+                .iter()
+                .chain(&expected[151..161])
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+
+        let mapped = packed.map(|el| match el {
+            Test::A(a, ..) => Lowered::Word(a.into()),
+            _ => Lowered::Word(0),
+        });
+        assert!(matches!(
+            mapped.get(150),
+            Some(lowered_types::Ref::Word(50))
+        ));
+        assert!(matches!(mapped.get(10), Some(lowered_types::Ref::Word(0))));
+    }
+
+    #[test]
+    fn tag_pack() {
+        let elements: Vec<_> = (0..1000u32)
+            .map(|i| match i % 7 {
+                0 | 3 => Test::C,
+                1 => Test::B {
+                    foo: i as u16,
+                    bar: 0,
+                },
+                _ => Test::A(i as u8, 0, 0, 0),
+            })
+            .collect();
+        let mut packed: TagPack<_> = elements.iter().copied().collect();
+        assert_eq!(packed, elements);
+        for i in [0, 255, 256, 513, 999] {
+            assert_eq!(
+                packed.get(i).map(|el| el == elements[i].to_ref()),
+                Some(true)
+            );
+        }
+
+        for expected in elements[700..].iter().rev() {
+            assert_eq!(packed.pop().as_ref(), Some(expected));
+        }
+        assert_eq!(packed, elements[..700]);
+        let checkpoint = packed.checkpoint();
+        packed.extend(elements[..300].iter().copied());
+        packed.rollback_to(checkpoint);
+        assert_eq!(packed, elements[..700]);
+
+        packed.retain_variants(&VariantSet::single(TestVariant::B));
+        assert_eq!(packed.len(), 100);
+        assert!(matches!(packed.get(99), Some(TestRef::B { foo: 694, .. })));
+
+        // Ranks past the first superblock add its base to the block offsets
+        let test = |i: u32| match i % 3 {
+            0 => Test::A(i as u8, 0, 0, 0),
+            _ => Test::B {
+                foo: i as u16,
+                bar: (i >> 16) as u16,
+            },
+        };
+        let mut packed: TagPack<_> = (0..65_537).map(test).collect();
+        let checkpoint = packed.checkpoint();
+        packed.extend((65_537..140_000).map(test));
+        for i in [65_535, 65_536, 65_537, 131_071, 131_072, 139_999] {
+            assert_eq!(packed.get(i as usize), Some(test(i).to_ref()));
+        }
+        packed.rollback_to(checkpoint);
+        assert_eq!(packed.pop(), Some(test(65_536)));
+        assert_eq!(packed.pop(), Some(test(65_535)));
+        packed.extend((65_535..70_000).map(test));
+        assert_eq!(packed.get(69_999), Some(test(69_999).to_ref()));
+    }
+
+    #[test]
+    fn bag() {
+        let drops = Rc::new(Cell::new(0));
+        let mut bag: PackBag<_> = [
+            Noisy::Quiet,
+            Noisy::Loud(drops.clone()),
+            Noisy::Loud(drops.clone()),
+            Noisy::Quiet,
+            Noisy::Loud(drops.clone()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(bag.len(), 5);
+        assert_eq!(bag.count(noisy_types::Variant::Loud), 3);
+        assert_eq!(bag.bucket::<noisy_types::Quiet>().len(), 2);
+
+        drop(bag.swap_remove(noisy_types::Variant::Loud, 0));
+        assert_eq!(drops.get(), 1);
+        assert_eq!(bag.iter().count(), 4);
+
+        let mut drained = bag.drain();
+        assert!(matches!(drained.next(), Some(Noisy::Loud(_))));
+        drop(drained);
+        assert_eq!(drops.get(), 3);
+        assert!(bag.is_empty());
+
+        bag.insert(Noisy::Loud(drops.clone()));
+        drop(bag);
+        assert_eq!(drops.get(), 4);
+
+        let mut bag: PackBag<_> = [Test::A(1, 2, 3, 4), Test::C, Test::A(5, 6, 7, 8)]
+            .into_iter()
+            .collect();
+        for el in bag.iter_mut() {
+            if let TestMut::A(a, ..) = el {
+                *a += 1;
+            }
+        }
+        assert_eq!(bag.swap_remove(TestVariant::A, 0), Test::A(2, 2, 3, 4));
+        assert_eq!(bag.bucket::<test_types::A>()[0].0, 6);
+        assert_eq!(
+            bag.drain().collect::<Vec<_>>(),
+            [Test::A(6, 6, 7, 8), Test::C]
+        );
+    }
+
+    #[test]
+    fn const_new() {
+        const EMPTY: Pack<Test> = Pack::new();
+        let mut packed = EMPTY;
+        assert_eq!(packed.capacity(), 0);
+        packed.push(Test::C);
+        assert_eq!(packed, [Test::C]);
+        let bag = const { PackBag::<Owned>::new() };
+        assert!(bag.is_empty());
+    }
+
+    #[test]
+    fn shared_buckets() {
+        use mixed_types::Variant as MixedVariant;
+        use packed_enum::Variant as _;
+
+        assert_eq!(
+            MixedVariant::Shared.bucket_index(),
+            MixedVariant::Id.bucket_index()
+        );
+        assert_eq!(
+            MixedVariant::Float.bucket_index(),
+            MixedVariant::Word.bucket_index()
+        );
+        assert!(!MixedVariant::Empty.shares_bucket());
+
+        let rc = Rc::new(());
+        let mut packed: Pack<_> = [
+            Mixed::Id(1),
+            Mixed::Word(2),
+            Mixed::Shared(rc.clone()),
+            Mixed::Float(3.0),
+            Mixed::Id(4),
+            Mixed::Empty,
+        ]
+        .into_iter()
+        .collect();
+        assert!(matches!(packed.get(2), Some(mixed_types::Ref::Shared(_))));
+        assert_eq!(packed.count_of(MixedVariant::Id), 2);
+        assert_eq!(packed.counts()[MixedVariant::Word], 1);
+        assert_eq!(packed.iter_id().map(|id| id.0).collect::<Vec<_>>(), [1, 4]);
+        assert_eq!(packed.last_float().map(|float| float.0), Some(3.0));
+        assert_eq!(packed.clone(), packed);
+
+        let checkpoint = packed.checkpoint();
+        packed.push(Mixed::Shared(rc.clone()));
+        packed.push(Mixed::Word(5));
+        assert_eq!(Rc::strong_count(&rc), 3);
+        packed.rollback_to(checkpoint);
+        assert_eq!(Rc::strong_count(&rc), 2);
+        assert_eq!(packed.len(), 6);
+
+        packed.clear_variants(&VariantSet::single(MixedVariant::Id));
+        let expected = [
+            Mixed::Word(2),
+            Mixed::Shared(rc.clone()),
+            Mixed::Float(3.0),
+            Mixed::Empty,
+        ];
+        assert_eq!(packed, expected);
+        drop(expected);
+        assert_eq!(packed.ranks().position_of(MixedVariant::Shared, 0), Some(1));
+
+        let mapped = packed.map(|element| match element {
+            Mixed::Shared(_) => Mixed::Id(7),
+            element => element,
+        });
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert_eq!(
+            mapped,
+            [
+                Mixed::Word(2),
+                Mixed::Id(7),
+                Mixed::Float(3.0),
+                Mixed::Empty
+            ]
+        );
+
+        let packed: Pack<_> = [
+            Mixed::Id(1),
+            Mixed::Word(2),
+            Mixed::Shared(rc.clone()),
+            Mixed::Id(4),
+        ]
+        .into_iter()
+        .collect();
+        let unordered: Vec<_> = packed.into_iter_unordered().collect();
+        assert_eq!(
+            unordered,
+            [
+                Mixed::Id(1),
+                Mixed::Shared(rc.clone()),
+                Mixed::Id(4),
+                Mixed::Word(2)
+            ]
+        );
+        drop(unordered);
+
+        let mut packed = Pack::new();
+        packed.push(Mixed::Shared(rc.clone()));
+        packed.push(Mixed::Id(8));
+        drop(packed);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn inline_payloads() {
+        use token_types::{Mut as TokenMut, Variant as TokenVariant};
+
+        let mut packed: Pack<_> = [
+            Token::Number(1),
+            Token::Ident("a".into()),
+            Token::Comma,
+            Token::Pair(2, 3),
+            Token::Number(4),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(packed.bucket::<token_types::Ident>().len(), 1);
+        assert_eq!(packed.count_number(), 2);
+        assert_eq!(packed.counts()[TokenVariant::Comma], 1);
+        assert_eq!(
+            packed.iter_number().map(|n| n.0).collect::<Vec<_>>(),
+            [1, 4]
+        );
+        if let Some(TokenMut::Number(n)) = packed.get_mut(4) {
+            *n += 1;
+        }
+        for element in packed.iter_mut() {
+            if let TokenMut::Pair(a, _) = element {
+                *a = 7;
+            }
+        }
+        packed.push_number(8);
+        assert_eq!(packed.pop(), Some(Token::Number(8)));
+        let expected = [
+            Token::Number(1),
+            Token::Ident("a".into()),
+            Token::Comma,
+            Token::Pair(7, 3),
+            Token::Number(5),
+        ];
+        assert_eq!(packed, expected);
+        assert_eq!(packed.clone(), packed);
+        assert_eq!(
+            packed.iter_unordered().collect::<Vec<_>>(),
+            [
+                &expected[1],
+                &expected[0],
+                &expected[2],
+                &expected[3],
+                &expected[4]
+            ]
+            .map(Token::to_ref)
+        );
+
+        packed.clear_variants(&VariantSet::single(TokenVariant::Number));
+        assert_eq!(packed, expected[1..4]);
+        let mapped = packed.map(|token| match token {
+            Token::Comma => Token::Number(0),
+            token => token,
+        });
+        assert_eq!(
+            mapped,
+            [
+                Token::Ident("a".into()),
+                Token::Number(0),
+                Token::Pair(7, 3)
+            ]
+        );
+
+        // With a 64-bit index, even the Rc fits in the entry
+        let rc = Rc::new(());
+        let mut packed = Pack::<Token, u64>::default();
+        packed.push(Token::Shared(rc.clone()));
+        let checkpoint = packed.checkpoint();
+        packed.push(Token::Shared(rc.clone()));
+        packed.push(Token::Comma);
+        assert_eq!(Rc::strong_count(&rc), 3);
+        packed.rollback_to(checkpoint);
+        assert_eq!(Rc::strong_count(&rc), 2);
+        packed.push(Token::Shared(rc.clone()));
+        let drained: Vec<_> = packed.into_iter_unordered().collect();
+        assert_eq!(drained.len(), 2);
+        drop(drained);
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        let mut packed = Pack::<Token, u64>::default();
+        packed.push(Token::Shared(rc.clone()));
+        drop(packed);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn boxed_payloads() {
+        use large_types::{Mut as LargeMut, Ref as LargeRef, Variant as LargeVariant};
+        use packed_enum::Variant as _;
+
+        assert!(LargeVariant::Table.is_boxed());
+        assert!(LargeVariant::Shared.is_boxed());
+        assert!(!LargeVariant::Name.is_boxed());
+        assert_eq!(
+            LargeVariant::Table.size_align().0,
+            std::mem::size_of::<Box<large_types::Table>>()
+        );
+
+        let rc = Rc::new(());
+        let mut packed = Pack::<Large>::new();
+        packed.push(Large::Table([1; 256]));
+        packed.push(Large::Shared(rc.clone()));
+        packed.push(Large::Small(2));
+        packed.push_payload(large_types::Table([3; 256]));
+        packed.push_with(|slot| slot.write(large_types::Name("a".into())));
+        assert_eq!(Rc::strong_count(&rc), 2);
+
+        if let Some(LargeMut::Table(table)) = packed.get_mut(3) {
+            table[0] = 4;
+        }
+        match packed.get(3) {
+            Some(LargeRef::Table(table)) => assert_eq!(table[..2], [4, 3]),
+            _ => panic!("expected a table"),
+        }
+        assert_eq!(
+            packed.iter_table().map(|t| t.0[0]).collect::<Vec<_>>(),
+            [1, 4]
+        );
+        assert_eq!(packed.last_table().map(|t| t.0[1]), Some(3));
+        assert_eq!(packed.payloads::<large_types::Name>().count(), 1);
+
+        #[derive(Default)]
+        struct Sum(u32);
+
+        impl LargeBatch for Sum {
+            fn table(&mut self, items: &mut [large_types::Table]) {
+                for item in items {
+                    item.0[255] = 9;
+                    self.0 += u32::from(item.0[0]);
+                }
+            }
+
+            fn shared(&mut self, items: &mut [large_types::Shared]) {
+                self.0 += items.len() as u32;
+            }
+
+            fn name(&mut self, _: &mut [large_types::Name]) {}
+
+            fn small(&mut self, _: &mut [large_types::Small]) {}
+        }
+
+        let mut sum = Sum::default();
+        packed.for_each_batch(&mut sum);
+        assert_eq!(sum.0, 6);
+        assert_eq!(packed.iter_table().map(|t| t.0[255]).sum::<u8>(), 18);
+
+        let cloned = packed.clone();
+        assert_eq!(cloned, packed);
+        assert_eq!(Rc::strong_count(&rc), 3);
+        drop(cloned);
+        assert_eq!(packed.pop(), Some(Large::Name("a".into())));
+        packed.push(Large::Shared(rc.clone()));
+        assert_eq!(Rc::strong_count(&rc), 3);
+        drop(packed);
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        // Copying a pack must not share the boxes between the copies
+        let mut packed = Pack::<Blob>::new();
+        packed.push(Blob::Bytes([5; 128]));
+        packed.push(Blob::Byte(6));
+        let mut cloned = packed.clone();
+        if let Some(blob_types::Mut::Bytes(bytes)) = cloned.get_mut(0) {
+            bytes[0] = 7;
+        }
+        match packed.get(0) {
+            Some(blob_types::Ref::Bytes(bytes)) => assert_eq!(bytes[0], 5),
+            _ => panic!("expected bytes"),
+        }
+
+        let mut bag = PackBag::new();
+        bag.insert(Blob::Bytes([8; 128]));
+        bag.insert(Blob::Byte(9));
+        assert_eq!(bag.len(), 2);
+        assert_eq!(
+            bag.swap_remove(blob_types::Variant::Bytes, 0),
+            Blob::Bytes([8; 128])
+        );
+    }
+
+    #[test]
+    fn tails() {
+        use lexeme_types::{Mut as LexemeMut, Ref as LexemeRef, Variant as LexemeVariant};
+        use packed_enum::Variant as _;
+
+        // Pointers to records are never shared with plain payloads
+        assert!(!LexemeVariant::Ident.shares_bucket());
+        assert_eq!(
+            LexemeVariant::Ident.size_align(),
+            LexemeVariant::Number.size_align()
+        );
+
+        let owner = Rc::new(());
+        let mut packed = Pack::<Lexeme>::new();
+        let mut expected = Vec::new();
+        for i in 0..200u32 {
+            let lexeme = match i % 4 {
+                0 => Lexeme::Ident("x".repeat(i as usize % 13)),
+                1 => Lexeme::Codes(i as u8, (0..i % 7).collect()),
+                2 => Lexeme::Literal {
+                    owner: owner.clone(),
+                    text: format!("\"{i}\""),
+                },
+                _ => Lexeme::Number(i.into()),
+            };
+            packed.push(lexeme.clone());
+            expected.push(lexeme);
+            // Leave records behind for the tail bucket to reclaim
+            if i % 5 == 4 {
+                assert_eq!(packed.pop(), expected.pop());
+            }
+        }
+        assert_eq!(packed, expected);
+        assert_eq!(Rc::strong_count(&owner), 1 + 2 * packed.count_literal());
+
+        match packed.get(0) {
+            Some(LexemeRef::Ident(ident)) => assert_eq!(ident, ""),
+            _ => panic!("expected an ident"),
+        }
+        if let Some(LexemeMut::Codes(_, codes)) = packed.get_mut(1) {
+            codes.fill(9);
+        }
+        expected[1] = Lexeme::Codes(1, vec![9]);
+        packed.push_ident("last".into());
+        expected.push(Lexeme::Ident("last".into()));
+        assert_eq!(packed.get(packed.len() - 1), Some(LexemeRef::Ident("last")));
+        assert_eq!(packed, expected);
+
+        // Variants with a tail have no method
+        #[derive(Default)]
+        struct Numbers(u64);
+
+        impl LexemeBatch for Numbers {
+            fn number(&mut self, items: &mut [lexeme_types::Number]) {
+                self.0 += items.iter().map(|number| number.0).sum::<u64>();
+            }
+
+            fn comma(&mut self, items: &mut [lexeme_types::Comma]) {}
+        }
+
+        let mut numbers = Numbers::default();
+        packed.for_each_batch(&mut numbers);
+        let sum = expected.iter().filter_map(|lexeme| match lexeme {
+            Lexeme::Number(n) => Some(n),
+            _ => None,
+        });
+        assert_eq!(numbers.0, sum.sum());
+
+        struct Upper;
+
+        impl LexemeVisitorMut for Upper {
+            fn visit_literal(&mut self, _: usize, v: &mut lexeme_types::Literal) {
+                v.text.make_ascii_uppercase();
+            }
+        }
+
+        #[derive(Default)]
+        struct Texts(Vec<(usize, String)>);
+
+        impl LexemeVisitor for Texts {
+            fn visit_literal(&mut self, index: usize, v: &lexeme_types::Literal) {
+                self.0.push((index, v.text.to_owned()));
+            }
+        }
+
+        packed.visit_mut(&mut Upper);
+        let mut texts = Texts::default();
+        packed.visit(&mut texts);
+        let literals: Vec<_> = expected
+            .iter()
+            .enumerate()
+            .filter_map(|(index, lexeme)| match lexeme {
+                Lexeme::Literal { text, .. } => Some((index, text.to_uppercase())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts.0, literals);
+
+        let cloned = packed.clone();
+        assert_eq!(cloned, expected);
+        packed.clear_variants(&VariantSet::single(LexemeVariant::Ident));
+        expected.retain(|lexeme| !matches!(lexeme, Lexeme::Ident(_)));
+        assert_eq!(packed, expected);
+        let (idents, numbers) = (cloned.count_ident(), cloned.count_number());
+        let mapped = cloned.map(|lexeme| match lexeme {
+            Lexeme::Number(n) => Lexeme::Ident(n.to_string()),
+            lexeme => lexeme,
+        });
+        assert_eq!(mapped.count_ident(), idents + numbers);
+        assert_eq!(mapped.count_number(), 0);
+        let unordered: Vec<_> = mapped.into_iter_unordered().collect();
+        assert_eq!(unordered.len(), expected.len() + idents);
+        drop((unordered, packed));
+
+        let mut bag = PackBag::new();
+        for lexeme in &expected {
+            bag.insert(lexeme.clone());
+        }
+        bag.insert(Lexeme::Literal {
+            owner: owner.clone(),
+            text: "bag".into(),
+        });
+        assert_eq!(bag.len(), expected.len() + 1);
+        assert!(bag.iter().any(|lexeme| lexeme
+            == LexemeRef::Literal {
+                owner: &owner,
+                text: "bag"
+            }));
+        let drained: Vec<_> = bag.drain().collect();
+        assert_eq!(drained.len(), expected.len() + 1);
+        drop((bag, drained, expected));
+        assert_eq!(Rc::strong_count(&owner), 1);
+
+        // The space of the last records is reused once they are removed
+        let mut packed = Pack::<Lexeme>::new();
+        let last_ident = |packed: &Pack<Lexeme>| match packed.get(packed.len() - 1) {
+            Some(LexemeRef::Ident(ident)) => ident.as_ptr(),
+            _ => panic!("expected an ident"),
+        };
+        // Leave room for every record so that none are moved
+        packed.push_ident("x".repeat(1000));
+        packed.pop();
+        packed.push_ident("kept".into());
+        packed.push_ident("popped".into());
+        let popped = last_ident(&packed);
+        packed.pop();
+        packed.push_ident("pushed".into());
+        assert_eq!(last_ident(&packed), popped);
+
+        let checkpoint = packed.checkpoint();
+        packed.push_ident("first".into());
+        let first = last_ident(&packed);
+        packed.push_codes(1, vec![1, 2, 3]);
+        packed.push_ident("second".into());
+        packed.rollback_to(checkpoint);
+        packed.push_ident("again".into());
+        assert_eq!(last_ident(&packed), first);
+
+        packed.push_codes(2, vec![4]);
+        packed.push_ident("cleared".into());
+        let cleared = last_ident(&packed);
+        packed.clear_variants(&VariantSet::single(LexemeVariant::Ident));
+        packed.push_ident("reused".into());
+        assert_eq!(last_ident(&packed), cleared);
+        assert_eq!(
+            packed,
+            [Lexeme::Codes(2, vec![4]), Lexeme::Ident("reused".into())]
+        );
+
+        // So is the space of the last record removed from a bag
+        let mut bag = PackBag::<Lexeme>::new();
+        let last_ident = |bag: &PackBag<Lexeme>| {
+            let idents = bag.iter().filter_map(|lexeme| match lexeme {
+                LexemeRef::Ident(ident) => Some(ident.as_ptr()),
+                _ => None,
+            });
+            idents.last().expect("expected an ident")
+        };
+        bag.insert(Lexeme::Ident("x".repeat(1000)));
+        drop(bag.swap_remove(LexemeVariant::Ident, 0));
+        bag.insert(Lexeme::Literal {
+            owner: owner.clone(),
+            text: "kept".into(),
+        });
+        bag.insert(Lexeme::Ident("removed".into()));
+        let removed = last_ident(&bag);
+        assert_eq!(
+            bag.swap_remove(LexemeVariant::Ident, 0),
+            Lexeme::Ident("removed".into())
+        );
+        bag.insert(Lexeme::Ident("inserted".into()));
+        assert_eq!(last_ident(&bag), removed);
+        let literal = bag.swap_remove(LexemeVariant::Literal, 0);
+        assert_eq!(Rc::strong_count(&owner), 2);
+        drop(literal);
+        assert_eq!(Rc::strong_count(&owner), 1);
+        assert_eq!(bag.len(), 1);
+    }
 }
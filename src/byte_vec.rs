@@ -3,7 +3,9 @@ use std::{alloc::Layout, ptr::NonNull};
 
 pub struct ByteVec {
     ptr: NonNull<u8>,
+    /// Length in elements, so that zero-sized elements are still counted
     len: usize,
+    /// Capacity in bytes
     cap: usize,
 }
 
@@ -18,6 +20,12 @@ impl ByteVec {
     }
 }
 
+impl Default for ByteVec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct WrapVec<'a> {
     bytes: &'a mut ByteVec,
     size: usize,
@@ -43,37 +51,35 @@ impl<'a> WrapVec<'a> {
 
     /// Gets the size of the collection in elements
     pub const fn len(&self) -> usize {
-        if self.size == 0 {
-            0
-        } else {
-            self.bytes.len / self.size
-        }
+        self.bytes.len
     }
 
     pub fn set_len(&mut self, len: usize) {
-        if self.size == 0 {
-            return;
-        }
         assert!(len < isize::MAX as usize);
         assert!(len <= self.cap());
-        self.bytes.len = len * self.size;
+        self.bytes.len = len;
     }
 
     /// Gets the allocated capacity of the collection in elements
     pub const fn cap(&self) -> usize {
-        if self.size == 0 {
-            0
-        } else {
-            self.bytes.cap / self.size
+        match self.bytes.cap.checked_div(self.size) {
+            Some(cap) => cap,
+            None => usize::MAX,
         }
     }
 
-    const fn ptr(&self) -> *const u8 {
-        self.bytes.ptr.as_ptr().cast_const()
+    fn ptr(&self) -> *const u8 {
+        base_ptr(self.bytes, self.size, self.align).cast_const()
     }
 
     fn ptr_mut(&mut self) -> *mut u8 {
-        self.bytes.ptr.as_ptr()
+        base_ptr(self.bytes, self.size, self.align)
+    }
+
+    /// Gets a pointer to the first element, which is dangling but aligned if
+    /// nothing has been allocated
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr_mut()
     }
 
     /// Gets a pointer to the given `index` with elements of size `SIZE`
@@ -82,9 +88,6 @@ impl<'a> WrapVec<'a> {
     ///
     /// Panics if `index >= len`
     pub fn get(&self, index: usize) -> *const u8 {
-        if self.size == 0 {
-            return self.ptr();
-        }
         assert!(index < self.len());
         // SAFETY: index < len && len < isize::MAX
         unsafe { self.ptr().add(index * self.size) }
@@ -96,9 +99,6 @@ impl<'a> WrapVec<'a> {
     ///
     /// Panics if `index >= len`
     pub fn get_mut(&mut self, index: usize) -> *mut u8 {
-        if self.size == 0 {
-            return self.ptr_mut();
-        }
         assert!(index < self.len());
         // SAFETY: index < len && len < isize::MAX
         unsafe { self.ptr_mut().add(index * self.size) }
@@ -112,8 +112,11 @@ impl<'a> WrapVec<'a> {
     /// Allocate space for the given number of elements, doubling in size to
     /// avoid frequent reallocation
     pub fn maybe_grow_amortized(&mut self, new_cap: usize) {
+        if self.size == 0 {
+            return;
+        }
         if self.cap() == 0 {
-            self.alloc(4);
+            self.alloc(new_cap.max(4));
         } else if new_cap > self.cap() {
             self.alloc(new_cap.max(self.cap() * 2));
         }
@@ -126,6 +129,9 @@ impl<'a> WrapVec<'a> {
     /// Panics on allocation failure.
     pub fn alloc(&mut self, new_cap: usize) {
         assert!(self.len() <= new_cap);
+        if self.size == 0 {
+            return;
+        }
         let bytes = new_cap * self.size;
 
         let Ok(layout) = Layout::from_size_align(bytes, self.align) else {
@@ -137,7 +143,7 @@ impl<'a> WrapVec<'a> {
             return;
         }
 
-        let ptr = if self.cap() == 0 {
+        let ptr = if self.bytes.cap == 0 {
             // SAFETY: Layout has a nonzero size
             unsafe { std::alloc::alloc(layout) }
         } else {
@@ -164,7 +170,7 @@ impl<'a> WrapVec<'a> {
     /// Deallocates the allocated capacity, if any. This should be called in [`Drop`] by owners.
     pub fn dealloc(&mut self) {
         assert!(self.is_empty());
-        if self.cap() == 0 {
+        if self.bytes.cap == 0 {
             return;
         }
 
@@ -181,6 +187,77 @@ impl<'a> WrapVec<'a> {
     }
 }
 
+/// A read-only counterpart to [`WrapVec`]
+pub struct WrapRef<'a> {
+    bytes: &'a ByteVec,
+    size: usize,
+    align: usize,
+}
+
+impl<'a> WrapRef<'a> {
+    /// Creates a new read-only wrapper over the given byte vector
+    ///
+    /// # Safety
+    ///
+    /// Same as [`WrapVec::new`].
+    pub unsafe fn new(bytes: &'a ByteVec, size: usize, align: usize) -> Self {
+        Self { bytes, size, align }
+    }
+
+    /// Gets the size of the collection in elements
+    pub const fn len(&self) -> usize {
+        self.bytes.len
+    }
+
+    /// Gets a pointer to the first element, which is dangling but aligned if
+    /// nothing has been allocated
+    pub fn as_ptr(&self) -> *const u8 {
+        base_ptr(self.bytes, self.size, self.align).cast_const()
+    }
+
+    /// Gets a pointer to the given `index` with elements of size `SIZE`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`
+    pub fn get(&self, index: usize) -> *const u8 {
+        assert!(index < self.len());
+        let ptr = base_ptr(self.bytes, self.size, self.align).cast_const();
+        // SAFETY: index < len && len < isize::MAX
+        unsafe { ptr.add(index * self.size) }
+    }
+}
+
+impl WrapRef<'_> {
+    /// Copies the elements bitwise into a new byte vector with the same
+    /// element size and alignment
+    pub fn copy_to_new(&self) -> ByteVec {
+        let mut out = ByteVec::new();
+        let len = self.len();
+        if len > 0 {
+            let mut dst = unsafe { WrapVec::new(&mut out, self.size, self.align) };
+            dst.alloc(len);
+            dst.set_len(len);
+            let src = self.get(0);
+            let dst = dst.get_mut(0);
+            // SAFETY: Both vectors hold len elements of the same size
+            unsafe { dst.copy_from_nonoverlapping(src, len * self.size) };
+        }
+        out
+    }
+}
+
+/// Gets the base pointer of the byte vector. Without an allocation, as is
+/// always the case for zero-sized elements, this is a dangling pointer that
+/// still satisfies `align`.
+fn base_ptr(bytes: &ByteVec, size: usize, align: usize) -> *mut u8 {
+    if size == 0 || bytes.cap == 0 {
+        std::ptr::without_provenance_mut(align)
+    } else {
+        bytes.ptr.as_ptr()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
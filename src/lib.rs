@@ -4,27 +4,307 @@ mod byte_vec;
 
 mod pack;
 
-pub use pack::Pack;
+mod variant_map;
+
+mod variant_set;
+
+#[doc(hidden)]
+pub use byte_vec::ByteVec;
+pub use pack::{
+    Checkpoint, Drain, EntryIndex, EntryLayout, Handle, IntoIterUnordered, Pack, PackBag, Query,
+    Ranks, Runs, TagPack, Tags,
+};
 pub use packed_enum_derive::Packable;
+pub use variant_map::VariantMap;
+pub use variant_set::VariantSet;
 
 pub trait Packable {
     const VARIANT_COUNT: usize;
 
+    /// Whether the enum is [`Copy`], in which case its payloads can be
+    /// duplicated bitwise
+    const IS_COPY: bool;
+
+    /// Whether the enum itself implements [`Drop`], which must then run for
+    /// each element in addition to dropping the payload
+    const HAS_DROP_IMPL: bool;
+
+    /// Whether any variants share a bucket. See [`Variant::bucket_index`].
+    const SHARES_BUCKETS: bool;
+
+    /// Whether payloads small enough to fit in place of the bucket index of
+    /// an entry are stored there, which the derive enables for enums marked
+    /// `#[packed(inline)]`. See [`EntryLayout::INLINE_SPACE`].
+    const INLINE_PAYLOADS: bool;
+
     type Variant: Variant;
+
+    /// Storage for one bucket per variant, which the derive makes an array of
+    /// length `VARIANT_COUNT`, plus one for the records of variants with a
+    /// tail if there are any. See [`Variant::tail_layout`].
+    type Buckets: AsRef<[ByteVec]> + AsMut<[ByteVec]>;
+
+    /// Buckets with nothing allocated
+    const EMPTY_BUCKETS: Self::Buckets;
+
+    /// An array with one value per variant, which the derive makes an array
+    /// of length `VARIANT_COUNT`. See [`VariantMap`].
+    type Array<V>: AsRef<[V]> + AsMut<[V]> + IntoIterator<Item = V>;
+
+    /// Creates an array by calling `f` with the index of each variant in
+    /// order
+    fn array_from_fn<V>(f: impl FnMut(usize) -> V) -> Self::Array<V>;
+
+    /// The words of a bitset with one bit per variant, which the derive makes
+    /// an array of `VARIANT_COUNT.div_ceil(64)` words. See [`VariantSet`].
+    type Bits: Copy + AsRef<[u64]> + AsMut<[u64]>;
+
+    /// Bits with every bit clear
+    const EMPTY_BITS: Self::Bits;
+
     type Ref<'a>;
     type Mut<'a>;
 
     fn variant(&self) -> Self::Variant;
+
+    /// Borrows the fields of `self` in the same form as an element of a
+    /// [`Pack`]
+    fn to_ref(&self) -> Self::Ref<'_>;
+
+    /// Gets the number of elements in the tail of `self`, which is 0 if its
+    /// variant has no tail
+    fn tail_len(&self) -> usize;
+
+    /// Moves the payload of `self` into `dst` as its variant struct
+    ///
+    /// # Safety
+    ///
+    /// `dst` must be valid for writes and aligned for the variant struct of
+    /// `self.variant()`. If the variant has a tail, `dst` must instead already
+    /// hold a pointer to a record with room for [`Packable::tail_len`]
+    /// elements, which the payload is moved into.
     unsafe fn write(self, dst: *mut u8);
+
+    /// Moves a value out of the variant struct at `data`
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to an initialized variant struct of `variant`. The
+    /// value is moved out, so it must not be read or dropped again.
     unsafe fn read(variant: Self::Variant, data: *const u8) -> Self;
+
+    /// Disposes of a bitwise copy made with [`Packable::read`] of an element
+    /// that is still stored, freeing only the tail the read allocated
+    ///
+    /// # Safety
+    ///
+    /// `self` must have been read from an element that is still stored.
+    unsafe fn forget_copy(self);
+
+    /// Borrows the variant struct at `data`
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to an initialized variant struct of `variant` that
+    /// outlives `'a` and is not mutated for the duration of the borrow.
     unsafe fn read_ref<'a>(variant: Self::Variant, data: *const u8) -> Self::Ref<'a>;
+
+    /// Mutably borrows the variant struct at `data`
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to an initialized variant struct of `variant` that
+    /// outlives `'a` and is not otherwise accessed for the duration of the
+    /// borrow.
     unsafe fn read_mut<'a>(variant: Self::Variant, data: *mut u8) -> Self::Mut<'a>;
+
+    /// Drops `len` consecutive variant structs starting at `data`
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to `len` initialized variant structs of `variant`,
+    /// which must not be used again afterwards.
+    unsafe fn drop_in_place(variant: Self::Variant, data: *mut u8, len: usize);
 }
 
-pub trait Variant {
+/// Implemented by the generated struct for each variant of a [`Packable`]
+/// enum, which is how the variant's payload is laid out in its bucket
+pub trait Payload<T: Packable>: Sized {
+    const VARIANT: T::Variant;
+
+    /// Iterates over individual fields across a slice of payloads, such as
+    /// `foo_types::BColumn` for a variant `Foo::B`
+    type Column<'a>
+    where
+        Self: 'a;
+
+    /// The mutable counterpart to [`Payload::Column`]
+    type ColumnMut<'a>
+    where
+        Self: 'a;
+
+    fn column(items: &[Self]) -> Self::Column<'_>;
+    fn column_mut(items: &mut [Self]) -> Self::ColumnMut<'_>;
+}
+
+/// Passes whole buckets to the batch trait generated for a [`Packable`] enum,
+/// which is `FooBatch` for an enum `Foo`. Implemented by the derive for every
+/// type implementing that trait.
+pub trait DispatchBatch<B: ?Sized>: Packable {
+    /// Calls the method of `batch` for `variant` with the bucket at `data`
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to `len` initialized variant structs of `variant`
+    /// that are not otherwise accessed for the duration of the call.
+    /// Variants with a tail have no method, so they are ignored.
+    unsafe fn dispatch_batch(batch: &mut B, variant: Self::Variant, data: *mut u8, len: usize);
+}
+
+/// Passes elements to the visitor trait generated for a [`Packable`] enum,
+/// which is `FooVisitor` for an enum `Foo`. Implemented by the derive for
+/// every type implementing that trait.
+pub trait DispatchVisit<V: ?Sized>: Packable {
+    /// Calls the method of `visitor` for `variant` with the element at `data`
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to an initialized variant struct of `variant` that
+    /// is not mutated for the duration of the call, or to the pointer to its
+    /// record if the variant has a tail.
+    unsafe fn dispatch_visit(
+        visitor: &mut V,
+        index: usize,
+        variant: Self::Variant,
+        data: *const u8,
+    );
+}
+
+/// The mutable counterpart to [`DispatchVisit`] for `FooVisitorMut`
+pub trait DispatchVisitMut<V: ?Sized>: Packable {
+    /// Calls the method of `visitor` for `variant` with the element at `data`
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to an initialized variant struct of `variant` that
+    /// is not otherwise accessed for the duration of the call, or to the
+    /// pointer to its record if the variant has a tail.
+    unsafe fn dispatch_visit_mut(
+        visitor: &mut V,
+        index: usize,
+        variant: Self::Variant,
+        data: *mut u8,
+    );
+}
+
+pub trait Variant: Copy + Eq {
     fn as_index(&self) -> usize;
     fn size_align(&self) -> (usize, usize);
+
+    /// Whether dropping an element of this variant does anything
+    fn needs_drop(&self) -> bool;
     fn all() -> impl Iterator<Item = Self>;
+
+    /// Gets the index of the bucket the variant is stored in
+    ///
+    /// Variants sharing a bucket have the same size and alignment, and their
+    /// elements are told apart by the variant recorded in each entry. The
+    /// derive shares buckets when the enum is marked
+    /// `#[packed(share_buckets)]`, which saves allocations for enums with many
+    /// variants of a few sizes at the cost of per-variant bucket slices.
+    fn bucket_index(&self) -> usize {
+        self.as_index()
+    }
+
+    /// Whether any other variant is stored in the same bucket
+    fn shares_bucket(&self) -> bool {
+        false
+    }
+
+    /// Whether the variant stores a [`Box`] of its variant struct in its
+    /// bucket, which the derive does for variants marked `#[packed(boxed)]`
+    /// and for variants larger than `N` bytes in an enum marked
+    /// `#[packed(box_above = N)]`
+    ///
+    /// [`Variant::size_align`] is then the layout of the box, and the
+    /// pointers passed to [`Packable::read`] and the like point to it.
+    fn is_boxed(&self) -> bool {
+        false
+    }
+
+    /// Gets the size and alignment of the variant struct, even if it is boxed.
+    /// Variants with a tail have an unsized variant struct, so this is the
+    /// layout of their slot.
+    fn payload_size_align(&self) -> (usize, usize) {
+        self.size_align()
+    }
+
+    /// Gets how the records of the variant are laid out if it has a tail,
+    /// which the derive gives variants whose last field is a `Vec` or
+    /// `String` marked `#[packed(tail)]`
+    ///
+    /// The variant struct of such a variant is unsized, ending in the
+    /// elements of the tail, and is stored in a record of its own after the
+    /// length of the tail. Records are kept in one bucket for all variants,
+    /// and the bucket of the variant holds a pointer to each record, which is
+    /// what [`Packable::read`] and the like receive.
+    fn tail_layout(&self) -> Option<TailLayout> {
+        None
+    }
+}
+
+/// The layout of the records of a variant with a tail. See
+/// [`Variant::tail_layout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TailLayout {
+    /// The offset of the variant struct from the start of a record, which
+    /// holds the length of the tail
+    pub head: usize,
+    /// The offset of the first element of the tail from the start of a record
+    pub offset: usize,
+    /// The size of each element of the tail
+    pub size: usize,
+    /// The alignment of a record
+    pub align: usize,
+}
+
+impl TailLayout {
+    /// Lays out the records of a `repr(C)` variant struct with the given
+    /// alignment whose tail starts `tail` bytes in and has elements of `size`
+    /// bytes
+    pub const fn new(align: usize, tail: usize, size: usize) -> Self {
+        let head = size_of::<usize>().next_multiple_of(align);
+        Self {
+            head,
+            offset: head + tail,
+            size,
+            align: if align > align_of::<usize>() {
+                align
+            } else {
+                align_of::<usize>()
+            },
+        }
+    }
+
+    /// Gets the size of a record with a tail of `len` elements
+    pub const fn record_size(&self, len: usize) -> usize {
+        (self.offset + len * self.size).next_multiple_of(self.align)
+    }
+
+    /// Gets a pointer to the variant struct in the record that the slot at
+    /// `slot` points to, as a slice as long as the tail, which the derive
+    /// casts to a pointer to the unsized variant struct
+    ///
+    /// # Safety
+    ///
+    /// `slot` must point to an initialized slot of a variant with this
+    /// layout, whose record holds the length of its tail.
+    #[doc(hidden)]
+    pub unsafe fn head_ptr(&self, slot: *const u8) -> *mut [u8] {
+        let record = unsafe { slot.cast::<*mut u8>().read() };
+        let len = unsafe { record.cast::<usize>().read() };
+        std::ptr::slice_from_raw_parts_mut(unsafe { record.add(self.head) }, len)
+    }
 }
 
 /*
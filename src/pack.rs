@@ -1,38 +1,165 @@
-use crate::{
-    byte_vec::{ByteVec, WrapVec},
-    Packable, Variant,
-};
-use std::marker::PhantomData;
+use crate::{byte_vec::WrapVec, Packable, Payload, Variant, VariantMap};
+use std::{marker::PhantomData, mem::MaybeUninit};
 
 macro_rules! bucket {
     ($s:ident, $v:ident) => {{
-        let (size, align) = $v.size_align();
-        let bucket = &mut $s.buckets[$v.as_index()];
-        unsafe { WrapVec::new(bucket, size, align) }
+        let (size, align) = $crate::Variant::size_align(&$v);
+        let bucket = &mut $s.buckets.as_mut()[$crate::Variant::bucket_index(&$v)];
+        unsafe { $crate::byte_vec::WrapVec::new(bucket, size, align) }
     }};
 }
 
-pub struct Pack<T: Packable> {
-    // TODO: Memory compaction of entries
-    entries: Vec<Entry<T>>,
-    // TODO: Use array instead when generic_const_exprs is stable
-    buckets: Vec<ByteVec>,
+macro_rules! bucket_ref {
+    ($s:ident, $v:ident) => {{
+        let (size, align) = $crate::Variant::size_align(&$v);
+        let bucket = &$s.buckets.as_ref()[$crate::Variant::bucket_index(&$v)];
+        unsafe { $crate::byte_vec::WrapRef::new(bucket, size, align) }
+    }};
+}
+
+mod map;
+
+mod rank;
+pub use rank::Ranks;
+
+mod handle;
+pub use handle::Handle;
+use handle::History;
+
+mod checkpoint;
+pub use checkpoint::Checkpoint;
+
+mod traits;
+
+mod dispatch;
+
+mod query;
+pub use query::Query;
+
+mod filter;
+
+mod bag;
+pub use bag::{Drain, PackBag};
+
+mod index;
+pub use index::EntryIndex;
+
+mod tail;
+use tail::{dealloc_records, release_records, reserve_record, shrink_records, write_element};
+
+mod entries;
+use entries::{stores_inline, EntryStore};
+pub use entries::{EntryLayout, Runs, Tags};
+
+/// A [`Pack`] that stores only the variant tag of each element. See
+/// [`Tags`].
+pub type TagPack<T> = Pack<T, Tags>;
+
+/// A sequence of enum values whose payloads are stored in one bucket per
+/// variant
+///
+/// `L` selects how the variant and bucket index of each element are stored.
+/// See [`EntryLayout`].
+pub struct Pack<T: Packable, L: EntryLayout = u32> {
+    entries: L::Store<T>,
+    buckets: T::Buckets,
+    history: History,
     marker: PhantomData<T>,
 }
 
 impl<T: Packable> Pack<T> {
     /// Creates a new, empty collection.
-    pub fn new() -> Self {
-        let buckets: Vec<_> = std::iter::repeat_with(ByteVec::new)
-            .take(T::VARIANT_COUNT)
-            .collect();
+    ///
+    /// Use [`Pack::default`] for an [`EntryLayout`] other than `u32`.
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            buckets: T::EMPTY_BUCKETS,
+            history: History::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates a new collection with space for at least the given number of
+    /// elements of each variant
+    pub fn with_variant_capacities(capacities: VariantMap<T, usize>) -> Self {
+        let mut out = Self::new();
+        out.reserve_variants(capacities);
+        out
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Pack<T, L> {
+    fn empty() -> Self {
         Self {
-            buckets,
-            entries: vec![],
+            buckets: T::EMPTY_BUCKETS,
+            entries: Default::default(),
+            history: History::new(),
             marker: PhantomData,
         }
     }
 
+    /// Reserves space for at least the given number of additional elements of
+    /// each variant
+    pub fn reserve_variants(&mut self, additional: VariantMap<T, usize>) {
+        self.entries.reserve(additional.values().sum());
+        let mut per_bucket = vec![0; T::VARIANT_COUNT];
+        for (variant, &additional) in additional.iter() {
+            per_bucket[variant.bucket_index()] += additional;
+        }
+        for variant in bucket_variants::<T>() {
+            let additional = per_bucket[variant.bucket_index()];
+            if additional > 0 && !Self::is_inline(variant) {
+                bucket!(self, variant).maybe_grow_by(additional);
+            }
+        }
+    }
+
+    /// Frees the memory the entries and buckets have beyond what their
+    /// elements need, and packs the records of tails together. Handles are
+    /// unaffected.
+    pub fn compact(&mut self) {
+        self.entries.shrink_to_fit();
+        for variant in bucket_variants::<T>() {
+            let mut bucket = bucket!(self, variant);
+            let len = bucket.len();
+            bucket.alloc(len);
+        }
+        shrink_records::<T>(&mut self.buckets);
+        self.history.shrink_to_fit();
+    }
+
+    /// Gets the maximum number of elements of any one variant, which is
+    /// limited by the index type of the layout `L`
+    pub const fn max_capacity() -> usize {
+        L::MAX_INDEX.saturating_add(1)
+    }
+
+    /// Counts the elements of each variant
+    pub fn counts(&self) -> VariantMap<T, usize> {
+        if T::SHARES_BUCKETS || Self::any_inline() {
+            let mut counts = VariantMap::default();
+            for Entry { variant, .. } in self.entries.iter() {
+                counts[variant] += 1;
+            }
+            return counts;
+        }
+        VariantMap::from_fn(|variant: T::Variant| bucket_ref!(self, variant).len())
+    }
+
+    /// Counts the elements of the given variant, which goes through the
+    /// entries if it shares its bucket or is stored inline
+    pub fn count_of(&self, variant: T::Variant) -> usize {
+        if variant.shares_bucket() || Self::is_inline(variant) {
+            self.entries
+                .iter()
+                .filter(|entry| entry.variant == variant)
+                .count()
+        } else {
+            bucket_ref!(self, variant).len()
+        }
+    }
+
     /// Returns the number of elements in the slice
     pub fn len(&self) -> usize {
         self.entries.len()
@@ -42,51 +169,664 @@ impl<T: Packable> Pack<T> {
         self.len() == 0
     }
 
+    /// Returns the number of elements that can be pushed without reallocating
+    /// the entries. Buckets grow separately; see [`Pack::reserve_variants`].
     pub fn capacity(&self) -> usize {
         self.entries.capacity()
     }
 
-    pub fn push(&mut self, element: T) {
+    /// Appends an element and returns a reference to it
+    pub fn push(&mut self, element: T) -> T::Mut<'_> {
         let variant = element.variant();
+        let record = reserve_record(&mut self.buckets, &element);
+        self.push_slot(variant, |dst| unsafe {
+            write_element(element, record, dst)
+        })
+    }
+
+    /// Appends an element by initializing its payload in place
+    ///
+    /// `f` receives uninitialized memory in the variant's bucket and must
+    /// return the reference produced by initializing it, typically through
+    /// [`MaybeUninit::write`] or by writing each field and calling
+    /// [`MaybeUninit::assume_init_mut`]. This avoids moving large payloads
+    /// through the stack. Payloads stored inline are small, so they are
+    /// initialized on the stack and then moved into their entry, while boxed
+    /// payloads are initialized in their box.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` returns a reference to anything other than the slot it
+    /// was given.
+    pub fn push_with<P: Payload<T>>(
+        &mut self,
+        f: impl for<'a> FnOnce(&'a mut MaybeUninit<P>) -> &'a mut P,
+    ) -> T::Mut<'_> {
+        let variant = P::VARIANT;
+        if variant.is_boxed() {
+            let mut boxed = Box::<P>::new_uninit();
+            let init: *const P = f(&mut boxed);
+            assert!(
+                std::ptr::eq(init, boxed.as_ptr()),
+                "push_with must return the initialized slot"
+            );
+            let boxed = unsafe { boxed.assume_init() };
+            return self.push_slot(variant, |dst| unsafe { dst.cast::<Box<P>>().write(boxed) });
+        }
+        if Self::is_inline(variant) {
+            let mut slot = MaybeUninit::<P>::uninit();
+            let init: *const P = f(&mut slot);
+            assert!(
+                std::ptr::eq(init, slot.as_ptr()),
+                "push_with must return the initialized slot"
+            );
+            let payload = unsafe { slot.assume_init() };
+            return self.push_slot(variant, |dst| unsafe { dst.cast::<P>().write(payload) });
+        }
         let mut bucket = bucket!(self, variant);
 
         let index = bucket.len();
+        let entry = Entry::new::<L>(variant, index);
         bucket.maybe_grow_by(1);
         bucket.set_len(index + 1);
+        let dst = bucket.get_mut(index).cast::<MaybeUninit<P>>();
+        // Don't count the element until it is initialized in case f panics
+        bucket.set_len(index);
 
-        let dst = bucket.get_mut(index);
-        unsafe { element.write(dst) };
+        // SAFETY: dst is within the capacity of the bucket, which is laid out
+        // for P, and MaybeUninit has no validity requirements
+        let slot = unsafe { &mut *dst };
+        let init = f(slot);
+        assert!(
+            std::ptr::eq(init, dst.cast()),
+            "push_with must return the initialized slot"
+        );
+        bucket.set_len(index + 1);
 
-        self.entries.push(Entry { variant, index });
+        self.push_entry(entry)
+    }
+
+    /// Appends an element given its payload, which skips matching on the
+    /// variant of an enum value
+    pub fn push_payload<P: Payload<T>>(&mut self, payload: P) -> T::Mut<'_> {
+        self.push_with(|slot| slot.write(payload))
+    }
+
+    /// Appends an element for each payload, reserving space in the bucket up
+    /// front from the size hint and moving each payload straight into it
+    pub fn extend_payloads<P: Payload<T>>(&mut self, payloads: impl IntoIterator<Item = P>) {
+        let payloads = payloads.into_iter();
+        let variant = P::VARIANT;
+        let additional = payloads.size_hint().0;
+        if !Self::is_inline(variant) {
+            bucket!(self, variant).maybe_grow_by(additional);
+        }
+        self.entries.reserve(additional);
+        for payload in payloads {
+            self.push_payload(payload);
+        }
+    }
+
+    /// Appends an element for each payload in `payloads`, leaving it empty.
+    /// Payloads stored in their bucket as they are, rather than boxed or
+    /// inline, are moved in with a single copy.
+    pub fn append_payloads<P: Payload<T>>(&mut self, payloads: &mut Vec<P>) {
+        let variant = P::VARIANT;
+        let len = payloads.len();
+        if variant.is_boxed() || Self::is_inline(variant) {
+            self.extend_payloads(payloads.drain(..));
+            return;
+        }
+        if len == 0 {
+            return;
+        }
+        let mut bucket = bucket!(self, variant);
+        let start = bucket.len();
+        // Panics before anything is written if the last index doesn't fit
+        Entry::<T>::new::<L>(variant, start + len - 1);
+        bucket.maybe_grow_by(len);
+        bucket.set_len(start + len);
+        let dst = bucket.get_mut(start).cast::<P>();
+        unsafe {
+            dst.copy_from_nonoverlapping(payloads.as_ptr(), len);
+            // The payloads were moved bitwise into the bucket
+            payloads.set_len(0);
+        }
+        self.entries.push_consecutive(variant, start, len);
+        self.history.push_many(len);
+    }
+
+    /// Appends an element, with `write` initializing its slot in the entry
+    /// or at the end of its bucket
+    fn push_slot(&mut self, variant: T::Variant, write: impl FnOnce(*mut u8)) -> T::Mut<'_> {
+        if !Self::is_inline(variant) {
+            let entry = self.push_bucket_with(variant, write);
+            return self.push_entry(entry);
+        }
+        let position = self.len();
+        self.entries.push(Entry { variant, index: 0 });
+        let dst = self.entries.inline_mut(position);
+        write(dst);
+        self.history.push();
+        unsafe { T::read_mut(variant, dst) }
+    }
+
+    fn push_entry(&mut self, entry: Entry<T>) -> T::Mut<'_> {
+        self.entries.push(entry);
+        self.history.push();
+        let Entry { variant, index } = entry;
+        let mut bucket = bucket!(self, variant);
+        unsafe { T::read_mut(variant, bucket.get_mut(index)) }
+    }
+
+    /// Appends a slot to the bucket of `variant` for `write` to initialize
+    /// without recording an entry
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bucket is already at [`Pack::max_capacity`]
+    fn push_bucket_with(&mut self, variant: T::Variant, write: impl FnOnce(*mut u8)) -> Entry<T> {
+        let mut bucket = bucket!(self, variant);
+
+        let index = bucket.len();
+        let entry = Entry::new::<L>(variant, index);
+        bucket.maybe_grow_by(1);
+        bucket.set_len(index + 1);
+
+        write(bucket.get_mut(index));
+
+        entry
     }
 
     pub fn pop(&mut self) -> Option<T> {
+        // Inline payloads must be read before their entry is gone
+        let mut inline = None;
+        if Self::any_inline() {
+            let position = self.len().checked_sub(1)?;
+            let entry = self.entries.get(position)?;
+            if Self::is_inline(entry.variant) {
+                let src = self.entries.inline(position);
+                inline = Some(unsafe { T::read(entry.variant, src) });
+            }
+        }
         self.entries.pop().map(|entry| {
+            self.history.truncate(self.entries.len());
+            if let Some(element) = inline {
+                return element;
+            }
             let Entry { variant, index } = entry;
             let mut bucket = bucket!(self, variant);
             let src = bucket.get(index);
             bucket.set_len(index);
-            unsafe { T::read(variant, src) }
+            let element = unsafe { T::read(variant, src) };
+            unsafe { release_records::<T>(&mut self.buckets, [(variant, src)]) };
+            element
         })
     }
+
+    /// Gives the records of the elements at or after `position` whose variant
+    /// matches `removed` back to the tail bucket, before they are removed.
+    /// See [`release_records`].
+    fn release_tails(&mut self, position: usize, removed: impl Fn(T::Variant) -> bool) {
+        let has_tail = |variant: T::Variant| variant.tail_layout().is_some() && removed(variant);
+        if !<T::Variant as Variant>::all().any(has_tail) {
+            return;
+        }
+        let slots: Vec<_> = self
+            .entries
+            .iter()
+            .skip(position)
+            .filter(|entry| has_tail(entry.variant))
+            .map(|Entry { variant, index }| (variant, bucket_ref!(self, variant).get(index)))
+            .collect();
+        unsafe { release_records::<T>(&mut self.buckets, slots) };
+    }
+
+    /// Drops the elements of a bucket at or after `len` without updating the
+    /// entries
+    fn truncate_bucket(&mut self, variant: T::Variant, len: usize) {
+        let mut bucket = bucket!(self, variant);
+        let old_len = bucket.len();
+        if len >= old_len {
+            return;
+        }
+        let data = bucket.get_mut(len);
+        // Leak rather than double drop if a destructor panics
+        bucket.set_len(len);
+        if variant.needs_drop() {
+            unsafe { T::drop_in_place(variant, data, old_len - len) };
+        }
+    }
+
+    /// Drops the elements at or after position `len` one by one through
+    /// their entries, which is needed when variants share buckets. Elements
+    /// stored inline must already have been taken out.
+    fn truncate_shared(&mut self, len: usize) {
+        let removed: Vec<_> = self
+            .entries
+            .iter()
+            .skip(len)
+            .filter(|entry| !Self::is_inline(entry.variant))
+            .collect();
+        self.entries.truncate(len);
+        // Leak rather than double drop if a destructor panics
+        for &Entry { variant, index } in &removed {
+            let mut bucket = bucket!(self, variant);
+            if index < bucket.len() {
+                bucket.set_len(index);
+            }
+        }
+        for Entry { variant, index } in removed {
+            if variant.needs_drop() {
+                let mut bucket = bucket!(self, variant);
+                let (size, _) = variant.size_align();
+                unsafe {
+                    let data = bucket.as_mut_ptr().add(index * size);
+                    T::drop_in_place(variant, data, 1);
+                }
+            }
+        }
+    }
+
+    /// Gets the payloads of every element of variant `P` in bucket order
+    ///
+    /// # Panics
+    ///
+    /// Panics if `P` shares its bucket with another variant, is stored inline
+    /// or is boxed. Use [`Pack::payloads`] instead.
+    pub fn bucket<P: Payload<T>>(&self) -> &[P] {
+        let variant = P::VARIANT;
+        assert!(
+            Self::is_slice(variant),
+            "The variant is not stored as a slice of payloads"
+        );
+        let bucket = bucket_ref!(self, variant);
+        unsafe { std::slice::from_raw_parts(bucket.as_ptr().cast(), bucket.len()) }
+    }
+
+    /// Mutably gets the payloads of every element of variant `P` in bucket
+    /// order
+    ///
+    /// # Panics
+    ///
+    /// Panics if `P` shares its bucket with another variant, is stored inline
+    /// or is boxed.
+    pub fn bucket_mut<P: Payload<T>>(&mut self) -> &mut [P] {
+        let variant = P::VARIANT;
+        assert!(
+            Self::is_slice(variant),
+            "The variant is not stored as a slice of payloads"
+        );
+        let mut bucket = bucket!(self, variant);
+        let len = bucket.len();
+        unsafe { std::slice::from_raw_parts_mut(bucket.as_mut_ptr().cast(), len) }
+    }
+
+    /// Iterates over the payloads of every element of variant `P` in
+    /// insertion order, however it is stored
+    pub fn payloads<'a, P: Payload<T> + 'a>(&'a self) -> impl Iterator<Item = &'a P> {
+        let variant = P::VARIANT;
+        let (own, shared) = if variant.shares_bucket() || Self::is_inline(variant) {
+            (None, Some(self.entries.iter().enumerate()))
+        } else {
+            (Some(0..bucket_ref!(self, variant).len()), None)
+        };
+        let own = own
+            .into_iter()
+            .flatten()
+            .map(move |index| bucket_ref!(self, variant).get(index));
+        let shared = shared
+            .into_iter()
+            .flatten()
+            .filter(move |(_, entry)| entry.variant == variant)
+            .map(move |(position, entry)| self.payload(position, entry));
+        own.chain(shared)
+            .map(move |slot| unsafe { &*unbox_slot(variant, slot).cast::<P>() })
+    }
+
+    /// Gets the payload of the last element of variant `P`
+    pub fn last_payload<P: Payload<T>>(&self) -> Option<&P> {
+        let variant = P::VARIANT;
+        if variant.shares_bucket() || Self::is_inline(variant) {
+            return self.payloads::<P>().last();
+        }
+        let bucket = bucket_ref!(self, variant);
+        let last = bucket.len().checked_sub(1)?;
+        Some(unsafe { &*unbox_slot(variant, bucket.get(last)).cast::<P>() })
+    }
+
+    /// Gets the fields of every element of variant `P` as columns, such as
+    /// `pack.column::<foo_types::B>().bar()` to iterate over `Foo::B::bar`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `P` shares its bucket with another variant, is stored inline
+    /// or is boxed.
+    pub fn column<P: Payload<T>>(&self) -> P::Column<'_> {
+        P::column(self.bucket())
+    }
+
+    /// Mutably gets the fields of every element of variant `P` as columns.
+    /// See [`Pack::column`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `P` shares its bucket with another variant, is stored inline
+    /// or is boxed.
+    pub fn column_mut<P: Payload<T>>(&mut self) -> P::ColumnMut<'_> {
+        P::column_mut(self.bucket_mut())
+    }
+
+    /// Gets a reference to the element at the given position
+    pub fn get(&self, index: usize) -> Option<T::Ref<'_>> {
+        let entry = self.entries.get(index)?;
+        Some(unsafe { T::read_ref(entry.variant, self.payload(index, entry)) })
+    }
+
+    /// Gets a mutable reference to the element at the given position
+    pub fn get_mut(&mut self, index: usize) -> Option<T::Mut<'_>> {
+        let entry = self.entries.get(index)?;
+        Some(unsafe { T::read_mut(entry.variant, self.payload_mut(index, entry)) })
+    }
+
+    /// Iterates over the elements in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = T::Ref<'_>> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| unsafe {
+                T::read_ref(entry.variant, self.payload(position, entry))
+            })
+    }
+
+    /// Mutably iterates over the elements in insertion order
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = T::Mut<'_>> {
+        let Self {
+            entries, buckets, ..
+        } = self;
+        entries.iter_inline_mut().map(move |(entry, inline)| {
+            let data = Self::payload_in(buckets, inline, entry);
+            unsafe { T::read_mut(entry.variant, data) }
+        })
+    }
+
+    /// Whether elements of the variant are stored in their entries rather
+    /// than a bucket. See [`EntryLayout::INLINE_SPACE`].
+    fn is_inline(variant: T::Variant) -> bool {
+        stores_inline::<T, L>(variant)
+    }
+
+    /// Whether the payloads of the variant form a slice in a bucket of their
+    /// own
+    fn is_slice(variant: T::Variant) -> bool {
+        !variant.shares_bucket() && !variant.is_boxed() && !Self::is_inline(variant)
+    }
+
+    /// Whether elements of any variant are stored in their entries
+    fn any_inline() -> bool {
+        T::INLINE_PAYLOADS && <T::Variant as Variant>::all().any(Self::is_inline)
+    }
+
+    /// Gets a pointer to the payload of the element at `position`, given its
+    /// entry
+    fn payload(&self, position: usize, entry: Entry<T>) -> *const u8 {
+        let Entry { variant, index } = entry;
+        if Self::is_inline(variant) {
+            self.entries.inline(position)
+        } else {
+            bucket_ref!(self, variant).get(index)
+        }
+    }
+
+    /// Mutably gets a pointer to the payload of the element at `position`,
+    /// given its entry
+    fn payload_mut(&mut self, position: usize, entry: Entry<T>) -> *mut u8 {
+        let inline = if Self::is_inline(entry.variant) {
+            self.entries.inline_mut(position)
+        } else {
+            std::ptr::null_mut()
+        };
+        Self::payload_in(&mut self.buckets, inline, entry)
+    }
+
+    /// Gets a pointer to the payload of an element given a pointer to its
+    /// inline payload, for when the entries are borrowed separately
+    fn payload_in(buckets: &mut T::Buckets, inline: *mut u8, entry: Entry<T>) -> *mut u8 {
+        let Entry { variant, index } = entry;
+        if Self::is_inline(variant) {
+            return inline;
+        }
+        let (size, align) = variant.size_align();
+        let bucket = &mut buckets.as_mut()[variant.bucket_index()];
+        let mut bucket = unsafe { WrapVec::new(bucket, size, align) };
+        bucket.get_mut(index)
+    }
+
+    /// Moves the elements stored inline at or after position `from` whose
+    /// variant matches the predicate out of their entries, which the caller
+    /// must then remove
+    fn take_inline(&self, from: usize, mut f: impl FnMut(T::Variant) -> bool) -> Vec<T> {
+        if !Self::any_inline() {
+            return vec![];
+        }
+        self.entries
+            .iter()
+            .enumerate()
+            .skip(from)
+            .filter(|(_, entry)| Self::is_inline(entry.variant) && f(entry.variant))
+            .map(|(position, entry)| unsafe {
+                T::read(entry.variant, self.entries.inline(position))
+            })
+            .collect()
+    }
+
+    /// Lists the entries of elements stored in buckets in bucket order.
+    /// Variants sharing a bucket are interleaved in the order of their slots.
+    fn bucket_entries(&self) -> impl Iterator<Item = Entry<T>> + use<T, L> {
+        let shared = T::SHARES_BUCKETS.then(|| {
+            let mut entries: Vec<_> = self
+                .entries
+                .iter()
+                .filter(|entry| !Self::is_inline(entry.variant))
+                .collect();
+            entries.sort_by_key(|entry| entry.variant.bucket_index());
+            entries
+        });
+        let lens = (!T::SHARES_BUCKETS)
+            .then(|| VariantMap::<T, _>::from_fn(|variant| bucket_ref!(self, variant).len()));
+        let own = lens.into_iter().flat_map(|lens| {
+            <T::Variant as Variant>::all().flat_map(move |variant| {
+                (0..lens[variant]).map(move |index| Entry { variant, index })
+            })
+        });
+        own.chain(shared.into_iter().flatten())
+    }
+
+    /// Iterates over the elements in bucket order rather than insertion
+    /// order. Elements of each bucket are visited contiguously, which avoids
+    /// going through the entries when the order does not matter.
+    ///
+    /// When variants share buckets, the entries are sorted by bucket first.
+    /// Elements stored inline are visited last, in insertion order.
+    pub fn iter_unordered(&self) -> impl Iterator<Item = T::Ref<'_>> {
+        let buckets = self.bucket_entries().map(move |Entry { variant, index }| {
+            let bucket = bucket_ref!(self, variant);
+            unsafe { T::read_ref(variant, bucket.get(index)) }
+        });
+        let inline = Self::any_inline()
+            .then(|| self.entries.iter().enumerate())
+            .into_iter()
+            .flatten()
+            .filter(|(_, entry)| Self::is_inline(entry.variant))
+            .map(|(position, entry)| unsafe {
+                T::read_ref(entry.variant, self.entries.inline(position))
+            });
+        buckets.chain(inline)
+    }
+
+    /// Mutably iterates over the elements in bucket order. See
+    /// [`Pack::iter_unordered`].
+    pub fn iter_unordered_mut(&mut self) -> impl Iterator<Item = T::Mut<'_>> {
+        let bucket_entries = self.bucket_entries();
+        let Self {
+            entries, buckets, ..
+        } = self;
+        let buckets = bucket_entries.map(move |entry| {
+            let data = Self::payload_in(buckets, std::ptr::null_mut(), entry);
+            unsafe { T::read_mut(entry.variant, data) }
+        });
+        let inline = Self::any_inline()
+            .then(|| entries.iter_inline_mut())
+            .into_iter()
+            .flatten()
+            .filter(|(entry, _)| Self::is_inline(entry.variant))
+            .map(|(entry, inline)| unsafe { T::read_mut(entry.variant, inline) });
+        buckets.chain(inline)
+    }
+
+    /// Converts the collection into an iterator over its elements in bucket
+    /// order. See [`Pack::iter_unordered`].
+    pub fn into_iter_unordered(mut self) -> IntoIterUnordered<T, L> {
+        let (variants, shared): (Vec<_>, Vec<_>) = if T::SHARES_BUCKETS {
+            (vec![], self.bucket_entries().collect())
+        } else {
+            (<T::Variant as Variant>::all().collect(), vec![])
+        };
+        let inline = self.take_inline(0, |_| true);
+        self.entries.truncate(0);
+        IntoIterUnordered {
+            pack: self,
+            variants: variants.into_iter(),
+            current: None,
+            shared: shared.into_iter(),
+            inline: inline.into_iter(),
+        }
+    }
 }
 
-impl<T: Packable> Drop for Pack<T> {
+impl<T: Packable, L: EntryLayout> Drop for Pack<T, L> {
+    /// Drops the elements bucket by bucket rather than in insertion order.
+    /// Buckets whose variant needs no drop are deallocated without visiting
+    /// their elements.
     fn drop(&mut self) {
-        while self.pop().is_some() {}
-        for variant in <T::Variant as Variant>::all() {
+        let inline = self.take_inline(0, |_| true);
+        if T::SHARES_BUCKETS {
+            self.truncate_shared(0);
+        }
+        self.entries.truncate(0);
+        for variant in bucket_variants::<T>() {
+            self.truncate_bucket(variant, 0);
             bucket!(self, variant).dealloc();
         }
+        dealloc_records::<T>(&mut self.buckets);
+        drop(inline);
     }
 }
 
-impl<T: Packable> Default for Pack<T> {
+impl<T: Packable, L: EntryLayout> Default for Pack<T, L> {
     fn default() -> Self {
-        Self::new()
+        Self::empty()
     }
 }
 
-struct Entry<T: Packable> {
+/// An owning iterator over the elements of a [`Pack`] in bucket order
+///
+/// Created by [`Pack::into_iter_unordered`].
+pub struct IntoIterUnordered<T: Packable, L: EntryLayout = u32> {
+    pack: Pack<T, L>,
+    variants: std::vec::IntoIter<T::Variant>,
+    /// The variant being drained and the index of its next element
+    current: Option<(T::Variant, usize)>,
+    /// The entries left to drain in bucket order when variants share buckets
+    shared: std::vec::IntoIter<Entry<T>>,
+    /// The elements that were stored inline, drained after the buckets
+    inline: std::vec::IntoIter<T>,
+}
+
+impl<T: Packable, L: EntryLayout> Iterator for IntoIterUnordered<T, L> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pack = &mut self.pack;
+        if T::SHARES_BUCKETS {
+            let Some(Entry { variant, index }) = self.shared.next() else {
+                // Everything has been moved out
+                for variant in bucket_variants::<T>() {
+                    bucket!(pack, variant).set_len(0);
+                }
+                return self.inline.next();
+            };
+            let bucket = bucket!(pack, variant);
+            return Some(unsafe { T::read(variant, bucket.get(index)) });
+        }
+        loop {
+            if let Some((variant, index)) = self.current.as_mut() {
+                let variant = *variant;
+                let mut bucket = bucket!(pack, variant);
+                if *index < bucket.len() {
+                    let src = bucket.get(*index);
+                    *index += 1;
+                    return Some(unsafe { T::read(variant, src) });
+                }
+                // Everything in the bucket has been moved out
+                bucket.set_len(0);
+            }
+            let Some(variant) = self.variants.next() else {
+                return self.inline.next();
+            };
+            self.current = Some((variant, 0));
+        }
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Drop for IntoIterUnordered<T, L> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+/// Gets a pointer to the variant struct in a slot, following the box of a
+/// boxed variant
+///
+/// # Safety
+///
+/// `slot` must point to an initialized slot of `variant`.
+unsafe fn unbox_slot<V: Variant>(variant: V, slot: *const u8) -> *const u8 {
+    if variant.is_boxed() {
+        // A box of a sized type has the same layout as a pointer
+        unsafe { slot.cast::<*const u8>().read() }
+    } else {
+        slot
+    }
+}
+
+/// Lists one variant stored in each bucket
+fn bucket_variants<T: Packable>() -> impl Iterator<Item = T::Variant> {
+    <T::Variant as Variant>::all().filter(|variant| variant.bucket_index() == variant.as_index())
+}
+
+pub struct Entry<T: Packable> {
     variant: T::Variant,
     index: usize,
 }
+
+impl<T: Packable> Entry<T> {
+    /// # Panics
+    ///
+    /// Panics if `index` does not fit in the layout `L`
+    fn new<L: EntryLayout>(variant: T::Variant, index: usize) -> Self {
+        assert!(
+            index <= L::MAX_INDEX,
+            "Too many elements of one variant for the index type"
+        );
+        Self { variant, index }
+    }
+}
+
+impl<T: Packable> Clone for Entry<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Packable> Copy for Entry<T> {}
@@ -0,0 +1,244 @@
+use super::tail::{dealloc_records, release_records, reserve_record, write_element};
+use crate::{byte_vec::WrapVec, Packable, Payload, Variant};
+use std::marker::PhantomData;
+
+// Bags have no entries to tell variants apart, so unlike a pack they keep one
+// bucket per variant even when the enum shares buckets
+macro_rules! variant_bucket {
+    ($s:ident, $v:ident) => {{
+        let (size, align) = $v.size_align();
+        let bucket = &mut $s.buckets.as_mut()[$v.as_index()];
+        unsafe { $crate::byte_vec::WrapVec::new(bucket, size, align) }
+    }};
+}
+
+macro_rules! variant_bucket_ref {
+    ($s:ident, $v:ident) => {{
+        let (size, align) = $v.size_align();
+        let bucket = &$s.buckets.as_ref()[$v.as_index()];
+        unsafe { $crate::byte_vec::WrapRef::new(bucket, size, align) }
+    }};
+}
+
+/// An unordered collection of enum values stored only as their payloads, in
+/// one bucket per variant
+///
+/// Unlike a [`Pack`](crate::Pack), a bag does not remember the order elements
+/// were inserted in, so it spends nothing on entries. Elements are visited in
+/// bucket order and removed by their index within their variant's bucket.
+pub struct PackBag<T: Packable> {
+    buckets: T::Buckets,
+    marker: PhantomData<T>,
+}
+
+impl<T: Packable> PackBag<T> {
+    /// Creates a new, empty collection.
+    pub const fn new() -> Self {
+        Self {
+            buckets: T::EMPTY_BUCKETS,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of elements in the collection
+    pub fn len(&self) -> usize {
+        <T::Variant as Variant>::all()
+            .map(|variant| self.count(variant))
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Counts the elements of the given variant
+    pub fn count(&self, variant: T::Variant) -> usize {
+        variant_bucket_ref!(self, variant).len()
+    }
+
+    /// Adds an element to the end of its bucket and returns a reference to it
+    pub fn insert(&mut self, element: T) -> T::Mut<'_> {
+        let variant = element.variant();
+        let record = reserve_record(&mut self.buckets, &element);
+        let mut bucket = variant_bucket!(self, variant);
+
+        let index = bucket.len();
+        bucket.maybe_grow_by(1);
+        bucket.set_len(index + 1);
+
+        let dst = bucket.get_mut(index);
+        unsafe {
+            write_element(element, record, dst);
+            T::read_mut(variant, dst)
+        }
+    }
+
+    /// Removes the element at `index` in the bucket of `variant`, replacing
+    /// it with the last element of that bucket
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds for the bucket.
+    pub fn swap_remove(&mut self, variant: T::Variant, index: usize) -> T {
+        let (size, _) = variant.size_align();
+        let slot = variant_bucket_ref!(self, variant).get(index);
+        let removed = unsafe { T::read(variant, slot) };
+        unsafe { release_records::<T>(&mut self.buckets, [(variant, slot)]) };
+        let mut bucket = variant_bucket!(self, variant);
+        let last = bucket.len() - 1;
+        if index != last {
+            let src = bucket.get(last);
+            let dst = bucket.get_mut(index);
+            unsafe { dst.copy_from_nonoverlapping(src, size) };
+        }
+        bucket.set_len(last);
+        removed
+    }
+
+    /// Gets the payloads of every element of variant `P`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `P` is boxed.
+    pub fn bucket<P: Payload<T>>(&self) -> &[P] {
+        let variant = P::VARIANT;
+        assert!(
+            !variant.is_boxed(),
+            "The variant is not stored as a slice of payloads"
+        );
+        let bucket = variant_bucket_ref!(self, variant);
+        unsafe { std::slice::from_raw_parts(bucket.as_ptr().cast(), bucket.len()) }
+    }
+
+    /// Mutably gets the payloads of every element of variant `P`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `P` is boxed.
+    pub fn bucket_mut<P: Payload<T>>(&mut self) -> &mut [P] {
+        let variant = P::VARIANT;
+        assert!(
+            !variant.is_boxed(),
+            "The variant is not stored as a slice of payloads"
+        );
+        let mut bucket = variant_bucket!(self, variant);
+        let len = bucket.len();
+        unsafe { std::slice::from_raw_parts_mut(bucket.as_mut_ptr().cast(), len) }
+    }
+
+    /// Iterates over the elements in bucket order
+    pub fn iter(&self) -> impl Iterator<Item = T::Ref<'_>> {
+        <T::Variant as Variant>::all().flat_map(move |variant| {
+            let bucket = variant_bucket_ref!(self, variant);
+            (0..bucket.len()).map(move |i| unsafe { T::read_ref(variant, bucket.get(i)) })
+        })
+    }
+
+    /// Mutably iterates over the elements in bucket order
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = T::Mut<'_>> {
+        <T::Variant as Variant>::all()
+            .zip(self.buckets.as_mut())
+            .flat_map(|(variant, bucket)| {
+                let (size, align) = variant.size_align();
+                let mut bucket = unsafe { WrapVec::new(bucket, size, align) };
+                (0..bucket.len()).map(move |i| unsafe { T::read_mut(variant, bucket.get_mut(i)) })
+            })
+    }
+
+    /// Removes every element, returning them in bucket order
+    ///
+    /// The collection is empty as soon as this is called. Elements not
+    /// consumed by the iterator are dropped along with it.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        let buckets = std::mem::replace(&mut self.buckets, T::EMPTY_BUCKETS);
+        Drain {
+            buckets,
+            variants: <T::Variant as Variant>::all()
+                .collect::<Vec<_>>()
+                .into_iter(),
+            current: None,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Packable> Drop for PackBag<T> {
+    fn drop(&mut self) {
+        for variant in <T::Variant as Variant>::all() {
+            let mut bucket = variant_bucket!(self, variant);
+            let len = bucket.len();
+            if len > 0 {
+                let data = bucket.get_mut(0);
+                // Leak rather than double drop if a destructor panics
+                bucket.set_len(0);
+                if variant.needs_drop() {
+                    unsafe { T::drop_in_place(variant, data, len) };
+                }
+            }
+            bucket.dealloc();
+        }
+        dealloc_records::<T>(&mut self.buckets);
+    }
+}
+
+impl<T: Packable> Default for PackBag<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Packable> FromIterator<T> for PackBag<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut out = Self::new();
+        out.extend(iter);
+        out
+    }
+}
+
+impl<T: Packable> Extend<T> for PackBag<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for element in iter {
+            self.insert(element);
+        }
+    }
+}
+
+/// A draining iterator over the elements of a [`PackBag`] in bucket order
+///
+/// Created by [`PackBag::drain`].
+pub struct Drain<'a, T: Packable> {
+    buckets: T::Buckets,
+    variants: std::vec::IntoIter<T::Variant>,
+    /// The variant being drained and the index of its next element
+    current: Option<(T::Variant, usize)>,
+    marker: PhantomData<&'a mut PackBag<T>>,
+}
+
+impl<T: Packable> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((variant, index)) = self.current.as_mut() {
+                let variant = *variant;
+                let mut bucket = variant_bucket!(self, variant);
+                if *index < bucket.len() {
+                    let src = bucket.get(*index);
+                    *index += 1;
+                    return Some(unsafe { T::read(variant, src) });
+                }
+                // Everything in the bucket has been moved out
+                bucket.set_len(0);
+                bucket.dealloc();
+            }
+            self.current = Some((self.variants.next()?, 0));
+        }
+    }
+}
+
+impl<T: Packable> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+        dealloc_records::<T>(&mut self.buckets);
+    }
+}
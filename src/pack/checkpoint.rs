@@ -0,0 +1,71 @@
+use super::{EntryLayout, EntryStore, Pack};
+use crate::{Packable, VariantMap};
+use std::marker::PhantomData;
+
+/// A saved state of a [`Pack`] that it can be rolled back to
+///
+/// Created by [`Pack::checkpoint`].
+pub struct Checkpoint<T: Packable> {
+    len: usize,
+    bucket_lens: VariantMap<T, usize>,
+    pushes: u64,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Packable> Checkpoint<T> {
+    /// Gets the number of elements at the time of the checkpoint
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the collection was empty at the time of the checkpoint
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Pack<T, L> {
+    /// Records the current length of the collection so that later pushes
+    /// can be undone with [`Pack::rollback_to`]
+    pub fn checkpoint(&mut self) -> Checkpoint<T> {
+        // Start tracking removals so that rolling back can check for them
+        self.history.track(self.len());
+        Checkpoint {
+            len: self.len(),
+            bucket_lens: VariantMap::from_fn(|variant: T::Variant| {
+                bucket_ref!(self, variant).len()
+            }),
+            pushes: self.history.pushes(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Drops every element pushed since the checkpoint was made
+    ///
+    /// # Panics
+    ///
+    /// Panics if any element that was present at the time of the checkpoint
+    /// has since been removed.
+    pub fn rollback_to(&mut self, checkpoint: Checkpoint<T>) {
+        let Checkpoint {
+            len, bucket_lens, ..
+        } = checkpoint;
+        assert!(
+            self.history.present_before(checkpoint.pushes) == len,
+            "Elements older than the checkpoint were removed"
+        );
+
+        self.history.truncate(len);
+        self.release_tails(len, |_| true);
+        let inline = self.take_inline(len, |_| true);
+        if T::SHARES_BUCKETS {
+            self.truncate_shared(len);
+        } else {
+            self.entries.truncate(len);
+            for (variant, &len) in bucket_lens.iter() {
+                self.truncate_bucket(variant, len);
+            }
+        }
+        drop(inline);
+    }
+}
@@ -0,0 +1,125 @@
+use super::{unbox_slot, EntryLayout, EntryStore, Pack};
+use crate::{
+    byte_vec::{ByteVec, WrapVec},
+    DispatchBatch, DispatchVisit, DispatchVisitMut, Packable, Variant,
+};
+
+impl<T: Packable, L: EntryLayout> Pack<T, L> {
+    /// Calls the method of `batch` for each variant once with a slice of
+    /// every element of that variant, in bucket order. `B` is the batch trait
+    /// generated for `T`, such as `FooBatch` for an enum `Foo`.
+    ///
+    /// Variants that are not stored as a slice of their own, because they
+    /// share a bucket, are stored inline or are boxed, are gathered into a
+    /// temporary slice in insertion order and written back after their method
+    /// returns, even if it panics. Variants with a tail are skipped, since
+    /// their records differ in size and can't form a slice, so visit them
+    /// with [`Pack::visit_mut`] instead.
+    pub fn for_each_batch<B: ?Sized>(&mut self, batch: &mut B)
+    where
+        T: DispatchBatch<B>,
+    {
+        for variant in <T::Variant as Variant>::all() {
+            if variant.tail_layout().is_some() {
+                continue;
+            }
+            if !Self::is_slice(variant) {
+                self.batch_gathered(batch, variant);
+                continue;
+            }
+            let mut bucket = bucket!(self, variant);
+            let len = bucket.len();
+            unsafe { T::dispatch_batch(batch, variant, bucket.as_mut_ptr(), len) };
+        }
+    }
+
+    fn batch_gathered<B: ?Sized>(&mut self, batch: &mut B, variant: T::Variant)
+    where
+        T: DispatchBatch<B>,
+    {
+        let elements: Vec<_> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.variant == variant)
+            .collect();
+        // Nothing can move the payloads while the batch runs, as it has no
+        // access to the pack
+        let payloads: Vec<*mut u8> = elements
+            .into_iter()
+            .map(|(position, entry)| {
+                let slot = self.payload_mut(position, entry);
+                unsafe { unbox_slot(variant, slot).cast_mut() }
+            })
+            .collect();
+        let (size, align) = variant.payload_size_align();
+        let mut scratch = ByteVec::new();
+        let mut gathered = Gathered {
+            scratch: unsafe { WrapVec::new(&mut scratch, size, align) },
+            payloads,
+            size,
+        };
+        gathered.scratch.alloc(gathered.payloads.len());
+        gathered.scratch.set_len(gathered.payloads.len());
+        for (i, &src) in gathered.payloads.iter().enumerate() {
+            unsafe {
+                gathered
+                    .scratch
+                    .get_mut(i)
+                    .copy_from_nonoverlapping(src, size)
+            };
+        }
+
+        let len = gathered.payloads.len();
+        let data = gathered.scratch.as_mut_ptr();
+        unsafe { T::dispatch_batch(batch, variant, data, len) };
+    }
+
+    /// Calls the method of `visitor` for each element in insertion order,
+    /// along with its position. `V` is the visitor trait generated for `T`,
+    /// such as `FooVisitor` for an enum `Foo`.
+    pub fn visit<V: ?Sized>(&self, visitor: &mut V)
+    where
+        T: DispatchVisit<V>,
+    {
+        for (position, entry) in self.entries.iter().enumerate() {
+            let data = unsafe { unbox_slot(entry.variant, self.payload(position, entry)) };
+            unsafe { T::dispatch_visit(visitor, position, entry.variant, data) };
+        }
+    }
+
+    /// Calls the method of `visitor` for each element in insertion order. See
+    /// [`Pack::visit`].
+    pub fn visit_mut<V: ?Sized>(&mut self, visitor: &mut V)
+    where
+        T: DispatchVisitMut<V>,
+    {
+        let Self {
+            entries, buckets, ..
+        } = self;
+        for (position, (entry, inline)) in entries.iter_inline_mut().enumerate() {
+            let slot = Self::payload_in(buckets, inline, entry);
+            let data = unsafe { unbox_slot(entry.variant, slot).cast_mut() };
+            unsafe { T::dispatch_visit_mut(visitor, position, entry.variant, data) };
+        }
+    }
+}
+
+/// Payloads gathered for a batch, written back to where they came from when
+/// dropped
+struct Gathered<'a> {
+    scratch: WrapVec<'a>,
+    payloads: Vec<*mut u8>,
+    size: usize,
+}
+
+impl Drop for Gathered<'_> {
+    fn drop(&mut self) {
+        for (i, &dst) in self.payloads.iter().enumerate() {
+            unsafe { dst.copy_from_nonoverlapping(self.scratch.get(i), self.size) };
+        }
+        // The payloads were moved bitwise back to their slots
+        self.scratch.set_len(0);
+        self.scratch.dealloc();
+    }
+}
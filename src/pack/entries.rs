@@ -0,0 +1,566 @@
+use super::{Entry, EntryIndex};
+use crate::{Packable, Variant};
+use std::{marker::PhantomData, mem::MaybeUninit};
+
+/// Selects how a [`Pack`](crate::Pack) records the variant and bucket index
+/// of each element
+///
+/// Every [`EntryIndex`] type is a layout storing one entry per element with
+/// an index of that width. [`Runs`] stores runs of consecutive elements of the
+/// same variant instead, and [`Tags`] stores only the variant of each element.
+pub trait EntryLayout {
+    /// The largest bucket index the layout can record
+    const MAX_INDEX: usize;
+
+    /// The size and alignment of the bytes of each entry that can hold a
+    /// payload stored inline instead of its bucket index
+    const INLINE_SPACE: (usize, usize) = (0, 0);
+
+    #[doc(hidden)]
+    type Store<T: Packable>: EntryStore<T>;
+}
+
+impl<I: EntryIndex> EntryLayout for I {
+    const MAX_INDEX: usize = I::MAX;
+    const INLINE_SPACE: (usize, usize) = (size_of::<I>(), align_of::<I>());
+    type Store<T: Packable> = Vec<Compact<T, I>>;
+}
+
+/// Whether elements of the variant are stored in their entries rather than a
+/// bucket, which requires the enum to opt in with `#[packed(inline)]` and the
+/// payload to fit in [`EntryLayout::INLINE_SPACE`]. Variants with a tail keep
+/// the pointers to their records in a bucket.
+pub fn stores_inline<T: Packable, L: EntryLayout>(variant: T::Variant) -> bool {
+    let (size, align) = variant.size_align();
+    let (space, space_align) = L::INLINE_SPACE;
+    T::INLINE_PAYLOADS && size <= space && align <= space_align && variant.tail_layout().is_none()
+}
+
+/// An [`EntryLayout`] that stores runs of consecutive elements of the same
+/// variant, each as its variant, first bucket index and starting position
+///
+/// Pushing an element of the same variant as the last one only extends the
+/// last run, so streams made of long runs cost almost nothing beyond their
+/// payloads. Random access binary searches the runs, making
+/// [`Pack::get`](crate::Pack::get) O(log r) in the number of runs.
+///
+/// [`Pack::capacity`](crate::Pack::capacity) counts one element per spare
+/// run, since each element might start one, and reserving room for elements
+/// reserves a run for each. [`Pack::compact`](crate::Pack::compact) frees the
+/// runs left spare by elements that extended a run instead.
+pub struct Runs<I: EntryIndex = u32>(PhantomData<I>);
+
+impl<I: EntryIndex> EntryLayout for Runs<I> {
+    const MAX_INDEX: usize = I::MAX;
+    type Store<T: Packable> = RunStore<T, I>;
+}
+
+/// An [`EntryLayout`] that stores only the variant of each element
+///
+/// Since each bucket is kept in logical order, the bucket index of an element
+/// is the number of earlier elements of the same variant. A directory of
+/// those counts at the start of every block of 256 elements bounds the scan
+/// needed to find it, so random access is O(1) with a constant up to the block
+/// size while iteration, [`Pack::push`](crate::Pack::push) and
+/// [`Pack::pop`](crate::Pack::pop) stay O(1). The directory holds the counts
+/// of each block as 16-bit offsets from the start of its superblock of 65536
+/// elements, so each element costs the size of the variant tag, usually one
+/// byte, plus `VARIANT_COUNT / 128` bytes. See [`TagPack`](crate::TagPack).
+pub struct Tags;
+
+impl EntryLayout for Tags {
+    const MAX_INDEX: usize = usize::MAX;
+    type Store<T: Packable> = TagStore<T>;
+}
+
+/// The entries of a [`Pack`](crate::Pack) in logical order
+pub trait EntryStore<T: Packable>: Default + Clone {
+    fn len(&self) -> usize;
+
+    /// Gets the number of elements that can be pushed without reallocating
+    fn capacity(&self) -> usize;
+
+    fn reserve(&mut self, additional: usize);
+
+    /// Frees the capacity beyond what the entries need
+    fn shrink_to_fit(&mut self);
+
+    /// Appends an entry whose index fits in the layout
+    fn push(&mut self, entry: Entry<T>);
+
+    /// Appends `len` entries of `variant` whose indices count up from
+    /// `start`, all of which fit in the layout
+    fn push_consecutive(&mut self, variant: T::Variant, start: usize, len: usize) {
+        self.reserve(len);
+        for index in start..start + len {
+            self.push(Entry { variant, index });
+        }
+    }
+
+    fn pop(&mut self) -> Option<Entry<T>>;
+
+    fn get(&self, position: usize) -> Option<Entry<T>>;
+
+    fn truncate(&mut self, len: usize);
+
+    fn iter(&self) -> impl Iterator<Item = Entry<T>> + '_;
+
+    /// Iterates over the variants of the entries from `position` on
+    fn variants_from(&self, position: usize) -> impl Iterator<Item = T::Variant> + '_ {
+        self.iter().skip(position).map(|entry| entry.variant)
+    }
+
+    /// Keeps only the entries whose variant matches the predicate
+    fn retain(&mut self, f: impl FnMut(T::Variant) -> bool);
+
+    /// Gets a pointer to the inline payload of the entry at `position`. See
+    /// [`stores_inline`].
+    fn inline(&self, _position: usize) -> *const u8 {
+        std::ptr::null()
+    }
+
+    /// Mutably gets a pointer to the inline payload of the entry at
+    /// `position`
+    fn inline_mut(&mut self, _position: usize) -> *mut u8 {
+        std::ptr::null_mut()
+    }
+
+    /// Iterates over the entries along with pointers to their inline payloads
+    fn iter_inline_mut(&mut self) -> impl Iterator<Item = (Entry<T>, *mut u8)> + '_ {
+        self.iter().map(|entry| (entry, std::ptr::null_mut()))
+    }
+}
+
+/// An entry with its index narrowed to `I`, or with the payload in place of
+/// its index if the variant is stored inline
+pub struct Compact<T: Packable, I: EntryIndex> {
+    variant: T::Variant,
+    index: MaybeUninit<I>,
+}
+
+impl<T: Packable, I: EntryIndex> Compact<T, I> {
+    fn new(entry: Entry<T>) -> Self {
+        let index = if stores_inline::<T, I>(entry.variant) {
+            // Written by the pack right after pushing
+            MaybeUninit::uninit()
+        } else {
+            MaybeUninit::new(
+                I::from_usize(entry.index).expect("Index checked when pushing to the bucket"),
+            )
+        };
+        Self {
+            variant: entry.variant,
+            index,
+        }
+    }
+
+    fn get(&self) -> Entry<T> {
+        let index = if stores_inline::<T, I>(self.variant) {
+            0
+        } else {
+            // SAFETY: only inline entries leave the index uninitialized
+            unsafe { self.index.assume_init() }.to_usize()
+        };
+        Entry {
+            variant: self.variant,
+            index,
+        }
+    }
+}
+
+impl<T: Packable, I: EntryIndex> Clone for Compact<T, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Packable, I: EntryIndex> Copy for Compact<T, I> {}
+
+impl<T: Packable, I: EntryIndex> EntryStore<T> for Vec<Compact<T, I>> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.shrink_to_fit();
+    }
+
+    fn push(&mut self, entry: Entry<T>) {
+        self.push(Compact::new(entry));
+    }
+
+    fn pop(&mut self) -> Option<Entry<T>> {
+        self.pop().map(|entry| entry.get())
+    }
+
+    fn get(&self, position: usize) -> Option<Entry<T>> {
+        self.as_slice().get(position).map(Compact::get)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.truncate(len);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Entry<T>> + '_ {
+        self.as_slice().iter().map(Compact::get)
+    }
+
+    fn variants_from(&self, position: usize) -> impl Iterator<Item = T::Variant> + '_ {
+        self[position.min(self.len())..]
+            .iter()
+            .map(|entry| entry.variant)
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(T::Variant) -> bool) {
+        self.retain(|entry| f(entry.variant));
+    }
+
+    fn inline(&self, position: usize) -> *const u8 {
+        self[position].index.as_ptr().cast()
+    }
+
+    fn inline_mut(&mut self, position: usize) -> *mut u8 {
+        self[position].index.as_mut_ptr().cast()
+    }
+
+    fn iter_inline_mut(&mut self) -> impl Iterator<Item = (Entry<T>, *mut u8)> + '_ {
+        self.as_mut_slice()
+            .iter_mut()
+            .map(|entry| (entry.get(), entry.index.as_mut_ptr().cast()))
+    }
+}
+
+/// The entries of a [`Runs`] layout
+pub struct RunStore<T: Packable, I: EntryIndex> {
+    runs: Vec<Run<T, I>>,
+    len: usize,
+}
+
+struct Run<T: Packable, I: EntryIndex> {
+    variant: T::Variant,
+    /// The bucket index of the first element
+    start: I,
+    /// The logical position of the first element
+    position: usize,
+}
+
+impl<T: Packable, I: EntryIndex> Clone for Run<T, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Packable, I: EntryIndex> Copy for Run<T, I> {}
+
+impl<T: Packable, I: EntryIndex> RunStore<T, I> {
+    /// Gets the number of elements in the run at index `i`
+    fn run_len(&self, i: usize) -> usize {
+        let end = self.runs.get(i + 1).map_or(self.len, |next| next.position);
+        end - self.runs[i].position
+    }
+
+    /// Appends a run of `len` elements, merging it into the last run if it
+    /// continues it
+    fn push_run(&mut self, variant: T::Variant, start: usize, len: usize) {
+        let continues = self.runs.last().is_some_and(|last| {
+            last.variant == variant && last.start.to_usize() + self.len - last.position == start
+        });
+        if !continues {
+            self.runs.push(Run {
+                variant,
+                start: I::from_usize(start).expect("Index checked when pushing to the bucket"),
+                position: self.len,
+            });
+        }
+        self.len += len;
+    }
+}
+
+impl<T: Packable, I: EntryIndex> Default for RunStore<T, I> {
+    fn default() -> Self {
+        Self {
+            runs: vec![],
+            len: 0,
+        }
+    }
+}
+
+impl<T: Packable, I: EntryIndex> Clone for RunStore<T, I> {
+    fn clone(&self) -> Self {
+        Self {
+            runs: self.runs.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T: Packable, I: EntryIndex> EntryStore<T> for RunStore<T, I> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Counts one element for every spare run, which is how many are sure to
+    /// fit without reallocating. Elements that extend the last run take no
+    /// room at all, so more may fit.
+    fn capacity(&self) -> usize {
+        self.len + (self.runs.capacity() - self.runs.len())
+    }
+
+    /// Reserves a run for every element, since each might start one
+    fn reserve(&mut self, additional: usize) {
+        self.runs.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.runs.shrink_to_fit();
+    }
+
+    fn push(&mut self, entry: Entry<T>) {
+        self.push_run(entry.variant, entry.index, 1);
+    }
+
+    fn push_consecutive(&mut self, variant: T::Variant, start: usize, len: usize) {
+        self.push_run(variant, start, len);
+    }
+
+    fn pop(&mut self) -> Option<Entry<T>> {
+        let last = *self.runs.last()?;
+        self.len -= 1;
+        if self.len == last.position {
+            self.runs.pop();
+        }
+        Some(Entry {
+            variant: last.variant,
+            index: last.start.to_usize() + self.len - last.position,
+        })
+    }
+
+    fn get(&self, position: usize) -> Option<Entry<T>> {
+        if position >= self.len {
+            return None;
+        }
+        let i = self.runs.partition_point(|run| run.position <= position) - 1;
+        let run = self.runs[i];
+        Some(Entry {
+            variant: run.variant,
+            index: run.start.to_usize() + position - run.position,
+        })
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let kept = self.runs.partition_point(|run| run.position < len);
+        self.runs.truncate(kept);
+        self.len = len;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Entry<T>> + '_ {
+        self.runs.iter().enumerate().flat_map(move |(i, run)| {
+            let start = run.start.to_usize();
+            (start..start + self.run_len(i)).map(move |index| Entry {
+                variant: run.variant,
+                index,
+            })
+        })
+    }
+
+    fn variants_from(&self, position: usize) -> impl Iterator<Item = T::Variant> + '_ {
+        let first = self
+            .runs
+            .partition_point(|run| run.position <= position)
+            .saturating_sub(1);
+        (first..self.runs.len()).flat_map(move |i| {
+            let run = self.runs[i];
+            let start = run.position.max(position);
+            let end = start.max(run.position + self.run_len(i));
+            std::iter::repeat_n(run.variant, end - start)
+        })
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(T::Variant) -> bool) {
+        let old = std::mem::take(self);
+        for (i, run) in old.runs.iter().enumerate() {
+            if f(run.variant) {
+                self.push_run(run.variant, run.start.to_usize(), old.run_len(i));
+            }
+        }
+    }
+}
+
+/// The number of elements between rows of block offsets
+const BLOCK: usize = 256;
+
+/// The number of elements between rows of superblock bases, which is as many
+/// as a block offset can count
+const SUPERBLOCK: usize = 1 << 16;
+
+/// The entries of a [`Tags`] layout
+pub struct TagStore<T: Packable> {
+    tags: Vec<T::Variant>,
+    /// The number of elements in each bucket before the start of each
+    /// superblock, one row of `VARIANT_COUNT` counts per superblock
+    bases: Vec<usize>,
+    /// The number of elements in each bucket from the start of its superblock
+    /// to the start of each block, one row of `VARIANT_COUNT` counts per block
+    offsets: Vec<u16>,
+    /// The number of elements in each bucket
+    counts: Vec<usize>,
+}
+
+impl<T: Packable> TagStore<T> {
+    /// Counts the elements in the bucket of `variant` before `position`
+    fn rank(&self, variant: T::Variant, position: usize) -> usize {
+        let bucket = variant.bucket_index();
+        let block = position / BLOCK;
+        let base = self.bases[position / SUPERBLOCK * T::VARIANT_COUNT + bucket];
+        let before = base + usize::from(self.offsets[block * T::VARIANT_COUNT + bucket]);
+        let scanned = self.tags[block * BLOCK..position].iter();
+        before
+            + if T::SHARES_BUCKETS {
+                scanned.filter(|tag| tag.bucket_index() == bucket).count()
+            } else {
+                scanned.filter(|&&tag| tag == variant).count()
+            }
+    }
+}
+
+impl<T: Packable> Default for TagStore<T> {
+    fn default() -> Self {
+        Self {
+            tags: vec![],
+            bases: vec![],
+            offsets: vec![],
+            counts: vec![0; T::VARIANT_COUNT],
+        }
+    }
+}
+
+impl<T: Packable> Clone for TagStore<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tags: self.tags.clone(),
+            bases: self.bases.clone(),
+            offsets: self.offsets.clone(),
+            counts: self.counts.clone(),
+        }
+    }
+}
+
+impl<T: Packable> EntryStore<T> for TagStore<T> {
+    fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.tags.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.tags.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.tags.shrink_to_fit();
+        self.bases.shrink_to_fit();
+        self.offsets.shrink_to_fit();
+    }
+
+    fn push(&mut self, entry: Entry<T>) {
+        let bucket = entry.variant.bucket_index();
+        debug_assert_eq!(
+            entry.index, self.counts[bucket],
+            "Buckets are in logical order"
+        );
+        let len = self.tags.len();
+        if len.is_multiple_of(SUPERBLOCK) {
+            self.bases.extend_from_slice(&self.counts);
+        }
+        if len.is_multiple_of(BLOCK) {
+            let bases = &self.bases[self.bases.len() - T::VARIANT_COUNT..];
+            // A superblock has fewer elements than a u16 can count
+            let offsets = self
+                .counts
+                .iter()
+                .zip(bases)
+                .map(|(count, base)| (count - base) as u16);
+            self.offsets.extend(offsets);
+        }
+        self.counts[bucket] += 1;
+        self.tags.push(entry.variant);
+    }
+
+    fn pop(&mut self) -> Option<Entry<T>> {
+        let variant = self.tags.pop()?;
+        let len = self.tags.len();
+        if len.is_multiple_of(BLOCK) {
+            self.offsets.truncate(self.offsets.len() - T::VARIANT_COUNT);
+        }
+        if len.is_multiple_of(SUPERBLOCK) {
+            self.bases.truncate(self.bases.len() - T::VARIANT_COUNT);
+        }
+        let counted = &mut self.counts[variant.bucket_index()];
+        *counted -= 1;
+        Some(Entry {
+            variant,
+            index: *counted,
+        })
+    }
+
+    fn get(&self, position: usize) -> Option<Entry<T>> {
+        let &variant = self.tags.get(position)?;
+        Some(Entry {
+            variant,
+            index: self.rank(variant, position),
+        })
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.tags.len() {
+            return;
+        }
+        for variant in <T::Variant as Variant>::all() {
+            self.counts[variant.bucket_index()] = self.rank(variant, len);
+        }
+        self.tags.truncate(len);
+        self.offsets
+            .truncate(len.div_ceil(BLOCK) * T::VARIANT_COUNT);
+        self.bases
+            .truncate(len.div_ceil(SUPERBLOCK) * T::VARIANT_COUNT);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Entry<T>> + '_ {
+        let mut counts = vec![0; T::VARIANT_COUNT];
+        self.tags.iter().map(move |&variant| {
+            let counted = &mut counts[variant.bucket_index()];
+            *counted += 1;
+            Entry {
+                variant,
+                index: *counted - 1,
+            }
+        })
+    }
+
+    fn variants_from(&self, position: usize) -> impl Iterator<Item = T::Variant> + '_ {
+        self.tags[position.min(self.tags.len())..].iter().copied()
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(T::Variant) -> bool) {
+        let old = std::mem::take(self);
+        for entry in old.iter() {
+            if f(entry.variant) {
+                let index = self.counts[entry.variant.bucket_index()];
+                self.push(Entry { index, ..entry });
+            }
+        }
+    }
+}
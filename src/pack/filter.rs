@@ -0,0 +1,107 @@
+use super::{Entry, EntryLayout, EntryStore, Pack};
+use crate::{Packable, Variant, VariantSet};
+
+impl<T: Packable, L: EntryLayout> Pack<T, L> {
+    /// Iterates over the elements of the given variants in insertion order,
+    /// skipping other elements by their tag alone
+    pub fn iter_only(&self, variants: &VariantSet<T>) -> impl Iterator<Item = T::Ref<'_>> {
+        self.query().only(variants).iter()
+    }
+
+    /// Counts the elements of the given variants from their bucket lengths.
+    /// See [`Pack::count_of`].
+    pub fn count_in(&self, variants: &VariantSet<T>) -> usize {
+        variants.iter().map(|variant| self.count_of(variant)).sum()
+    }
+
+    /// Removes every element of the given variants
+    ///
+    /// Their buckets are dropped wholesale, so this is O(n) in the number of
+    /// entries plus the removed elements. Buckets shared with other variants
+    /// are compacted instead. Handles to the kept elements follow them to
+    /// their new positions.
+    pub fn clear_variants(&mut self, variants: &VariantSet<T>) {
+        let Some(first) = self
+            .entries
+            .iter()
+            .position(|entry| variants.contains(entry.variant))
+        else {
+            return;
+        };
+
+        self.history.retain(
+            self.entries
+                .iter()
+                .map(|entry| !variants.contains(entry.variant)),
+        );
+        self.release_tails(first, |variant| variants.contains(variant));
+        if variants.iter().any(|variant| variant.shares_bucket()) {
+            self.compact_variants(variants);
+            return;
+        }
+        let inline = self.take_inline(first, |variant| variants.contains(variant));
+        self.entries.retain(|variant| !variants.contains(variant));
+        for variant in variants.iter() {
+            self.truncate_bucket(variant, 0);
+        }
+        drop(inline);
+    }
+
+    /// Removes every element of the given variants by moving the kept
+    /// elements of each bucket down over the removed ones
+    fn compact_variants(&mut self, variants: &VariantSet<T>) {
+        // Inline payloads are moved out before their entries are cleared
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| {
+                let inline = Self::is_inline(entry.variant)
+                    .then(|| unsafe { T::read(entry.variant, self.entries.inline(position)) });
+                (entry, inline)
+            })
+            .collect();
+        self.entries.truncate(0);
+        let mut removed = Vec::new();
+        let mut next = vec![0; T::VARIANT_COUNT];
+        for (Entry { variant, index }, inline) in entries {
+            if let Some(element) = inline {
+                if variants.contains(variant) {
+                    removed.push(element);
+                } else {
+                    let position = self.len();
+                    self.entries.push(Entry { variant, index: 0 });
+                    unsafe { element.write(self.entries.inline_mut(position)) };
+                }
+                continue;
+            }
+            let mut bucket = bucket!(self, variant);
+            if variants.contains(variant) {
+                removed.push(unsafe { T::read(variant, bucket.get(index)) });
+                continue;
+            }
+            // Every slot below `index` has already been moved out or kept
+            let slot = &mut next[variant.bucket_index()];
+            if *slot != index {
+                let (size, _) = variant.size_align();
+                let src = bucket.get(index);
+                unsafe { bucket.get_mut(*slot).copy_from_nonoverlapping(src, size) };
+            }
+            self.entries.push(Entry {
+                variant,
+                index: *slot,
+            });
+            *slot += 1;
+        }
+        for variant in <T::Variant as Variant>::all() {
+            bucket!(self, variant).set_len(next[variant.bucket_index()]);
+        }
+        drop(removed);
+    }
+
+    /// Removes every element not of the given variants. See
+    /// [`Pack::clear_variants`].
+    pub fn retain_variants(&mut self, variants: &VariantSet<T>) {
+        self.clear_variants(&variants.complement());
+    }
+}
@@ -0,0 +1,245 @@
+use super::{EntryLayout, Pack};
+use crate::Packable;
+use std::{
+    fmt::{self, Debug, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Identifies an element of a [`Pack`] for as long as it is not removed
+///
+/// Created by [`Pack::push_handle`]. Unlike a position, a handle keeps
+/// referring to its element when earlier elements are removed, and never
+/// refers to a different element after its own is removed.
+pub struct Handle<T: Packable> {
+    /// The number of pushes made by the pack before this one
+    stamp: u64,
+    pack: NonZeroUsize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Packable> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Packable> Copy for Handle<T> {}
+
+impl<T: Packable> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.stamp, self.pack) == (other.stamp, other.pack)
+    }
+}
+
+impl<T: Packable> Eq for Handle<T> {}
+
+impl<T: Packable> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.stamp, self.pack).hash(state);
+    }
+}
+
+impl<T: Packable> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("stamp", &self.stamp)
+            .finish()
+    }
+}
+
+/// Tracks where the elements of a pack are so that handles can find them
+///
+/// Every element is stamped with the number of pushes made before it, so the
+/// stamps increase along the pack. Rather than storing every stamp, this
+/// keeps the runs of elements whose stamps are consecutive, which only
+/// removals other than popping the last element break up. Nothing is tracked
+/// until a handle or checkpoint is first made, since only they can observe
+/// removals.
+pub struct History {
+    /// Assigned once the first handle or checkpoint is made
+    id: Option<NonZeroUsize>,
+    pushes: u64,
+    runs: Vec<Run>,
+}
+
+/// Elements with consecutive stamps at consecutive positions
+#[derive(Clone, Copy)]
+struct Run {
+    stamp: u64,
+    position: usize,
+    len: usize,
+}
+
+impl Run {
+    const fn end(&self) -> usize {
+        self.position + self.len
+    }
+}
+
+impl History {
+    pub const fn new() -> Self {
+        Self {
+            id: None,
+            pushes: 0,
+            runs: vec![],
+        }
+    }
+
+    /// Records a push
+    pub fn push(&mut self) {
+        self.push_many(1);
+    }
+
+    /// Records `count` pushes
+    pub fn push_many(&mut self, count: usize) {
+        if self.id.is_some() {
+            Self::append(&mut self.runs, self.pushes, count);
+        }
+        self.pushes += count as u64;
+    }
+
+    /// Appends `len` elements stamped from `stamp` on to `runs`
+    fn append(runs: &mut Vec<Run>, stamp: u64, len: usize) {
+        match runs.last_mut() {
+            Some(last) if last.stamp + last.len as u64 == stamp => last.len += len,
+            _ => {
+                let position = runs.last().map_or(0, Run::end);
+                runs.push(Run {
+                    stamp,
+                    position,
+                    len,
+                });
+            }
+        }
+    }
+
+    /// Records that every element at or after `len` was removed
+    pub fn truncate(&mut self, len: usize) {
+        while self.runs.last().is_some_and(|last| last.position >= len) {
+            self.runs.pop();
+        }
+        if let Some(last) = self.runs.last_mut() {
+            last.len = last.len.min(len - last.position);
+        }
+    }
+
+    /// Records that the elements for which `kept` yields `false` were
+    /// removed, given one flag per element in order
+    pub fn retain(&mut self, kept: impl IntoIterator<Item = bool>) {
+        if self.id.is_none() {
+            return;
+        }
+        let stamps = self
+            .runs
+            .iter()
+            .flat_map(|run| run.stamp..run.stamp + run.len as u64);
+        let mut runs = Vec::new();
+        for (stamp, kept) in stamps.zip(kept) {
+            if kept {
+                Self::append(&mut runs, stamp, 1);
+            }
+        }
+        self.runs = runs;
+    }
+
+    /// Frees the memory of runs that were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.runs.shrink_to_fit();
+    }
+
+    /// Starts tracking the `len` elements of the pack if that hasn't
+    /// happened yet, and gets the ID of the pack
+    pub fn track(&mut self, len: usize) -> NonZeroUsize {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+        if let Some(id) = self.id {
+            return id;
+        }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let id = NonZeroUsize::new(id).expect("Pack ID overflow");
+        self.id = Some(id);
+        // Untracked elements may as well have been pushed consecutively
+        if len > 0 {
+            Self::append(&mut self.runs, self.pushes - len as u64, len);
+        }
+        id
+    }
+
+    /// Gets the number of pushes made so far
+    pub const fn pushes(&self) -> u64 {
+        self.pushes
+    }
+
+    /// Gets the run that an element stamped `stamp` would be in, which is the
+    /// last one starting at or before it
+    fn run(&self, stamp: u64) -> Option<&Run> {
+        let i = self.runs.partition_point(|run| run.stamp <= stamp);
+        self.runs.get(i.checked_sub(1)?)
+    }
+
+    /// Gets the position of the element stamped `stamp`, if it is present
+    fn position(&self, stamp: u64) -> Option<usize> {
+        let run = self.run(stamp)?;
+        let offset = usize::try_from(stamp - run.stamp).ok()?;
+        (offset < run.len).then(|| run.position + offset)
+    }
+
+    /// Counts the elements still present that were pushed before the given
+    /// number of pushes were made
+    pub fn present_before(&self, pushes: u64) -> usize {
+        self.run(pushes).map_or(0, |run| {
+            let offset = usize::try_from(pushes - run.stamp).unwrap_or(usize::MAX);
+            run.position + offset.min(run.len)
+        })
+    }
+
+    /// Gets the position of the element a handle was created for, if it is
+    /// still present
+    fn locate<T: Packable>(&self, handle: &Handle<T>) -> Option<usize> {
+        debug_assert_eq!(
+            Some(handle.pack),
+            self.id,
+            "Handle used with a different Pack"
+        );
+        self.position(handle.stamp)
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Pack<T, L> {
+    /// Appends an element and returns a handle to it. See [`Handle`].
+    pub fn push_handle(&mut self, element: T) -> Handle<T> {
+        let stamp = self.history.pushes;
+        self.push(element);
+        Handle {
+            stamp,
+            pack: self.history.track(self.len()),
+            marker: PhantomData,
+        }
+    }
+
+    /// Gets the number of runs of elements kept to locate handles
+    #[doc(hidden)]
+    pub fn history_len(&self) -> usize {
+        self.history.runs.len()
+    }
+
+    /// Gets the current position of the element a handle was created for, or
+    /// [`None`] if it has since been removed
+    pub fn position_by_handle(&self, handle: Handle<T>) -> Option<usize> {
+        self.history.locate(&handle)
+    }
+
+    /// Gets a reference to the element a handle was created for, or [`None`]
+    /// if it has since been removed
+    pub fn get_by_handle(&self, handle: Handle<T>) -> Option<T::Ref<'_>> {
+        self.get(self.position_by_handle(handle)?)
+    }
+
+    /// Gets a mutable reference to the element a handle was created for. See
+    /// [`Pack::get_by_handle`].
+    pub fn get_mut_by_handle(&mut self, handle: Handle<T>) -> Option<T::Mut<'_>> {
+        self.get_mut(self.position_by_handle(handle)?)
+    }
+}
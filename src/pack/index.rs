@@ -0,0 +1,38 @@
+/// An unsigned integer type that stores the bucket index of each element of a
+/// [`Pack`](crate::Pack)
+///
+/// Narrower types shrink the per-element overhead of a pack at the cost of a
+/// lower [`Pack::max_capacity`](crate::Pack::max_capacity). Combined with a
+/// one-byte tag, `u32` makes each entry eight bytes and `u16` four.
+pub trait EntryIndex: Copy {
+    /// The largest index representable by the type
+    const MAX: usize;
+
+    /// Converts an index, or returns [`None`] if it does not fit
+    fn from_usize(index: usize) -> Option<Self>;
+
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! entry_index {
+    ($($ty:ty),*) => {$(
+        impl EntryIndex for $ty {
+            const MAX: usize = if <$ty>::BITS >= usize::BITS {
+                usize::MAX
+            } else {
+                <$ty>::MAX as usize
+            };
+
+            fn from_usize(index: usize) -> Option<Self> {
+                Self::try_from(index).ok()
+            }
+
+            fn to_usize(self) -> usize {
+                // Only indices that fit in usize are ever stored
+                self as usize
+            }
+        }
+    )*};
+}
+
+entry_index!(u8, u16, u32, u64, usize);
@@ -0,0 +1,92 @@
+use super::{bucket_variants, EntryLayout, EntryStore, Pack};
+use crate::{Packable, Variant, VariantMap};
+
+impl<T: Packable, L: EntryLayout> Pack<T, L> {
+    /// Converts each element into an element of another packed enum, calling
+    /// `f` in insertion order.
+    ///
+    /// The first element of each variant reserves room for every element of
+    /// that variant in the bucket its result is stored in, so when each
+    /// variant maps onto a single target variant, every target bucket is
+    /// allocated once. If `f` panics, the elements not yet converted are
+    /// dropped.
+    pub fn map<U: Packable>(mut self, mut f: impl FnMut(T) -> U) -> Pack<U, L> {
+        let mut unreserved = self.counts();
+        let mut out = Pack::<U, L>::default();
+        out.entries.reserve(self.len());
+        let mut unmapped = Unmapped {
+            pack: &mut self,
+            moved: 0,
+        };
+        let pack = &*unmapped.pack;
+        for (position, entry) in pack.entries.iter().enumerate() {
+            let element = unsafe { T::read(entry.variant, pack.payload(position, entry)) };
+            unmapped.moved += 1;
+            out.push_mapped(&mut unreserved, entry.variant, f(element));
+        }
+        drop(unmapped);
+        out
+    }
+
+    /// Converts each borrowed element into an element of another packed
+    /// enum. See [`Pack::map`].
+    pub fn map_ref<U: Packable>(&self, mut f: impl FnMut(T::Ref<'_>) -> U) -> Pack<U, L> {
+        let mut unreserved = self.counts();
+        let mut out = Pack::<U, L>::default();
+        out.entries.reserve(self.len());
+        for (position, entry) in self.entries.iter().enumerate() {
+            let element = unsafe { T::read_ref(entry.variant, self.payload(position, entry)) };
+            out.push_mapped(&mut unreserved, entry.variant, f(element));
+        }
+        out
+    }
+
+    /// Appends an element converted from an element of `source`, first
+    /// reserving room in its bucket for the elements of `source` that nothing
+    /// was reserved for yet
+    fn push_mapped<S: Packable>(
+        &mut self,
+        unreserved: &mut VariantMap<S, usize>,
+        source: S::Variant,
+        element: T,
+    ) {
+        let additional = std::mem::take(&mut unreserved[source]);
+        let variant = element.variant();
+        if additional > 0 && !Self::is_inline(variant) {
+            bucket!(self, variant).maybe_grow_by(additional);
+        }
+        self.push(element);
+    }
+}
+
+/// Drops the elements of a pack at or after position `moved` once
+/// [`Pack::map`] is done with it, the others having been moved out
+struct Unmapped<'a, T: Packable, L: EntryLayout> {
+    pack: &'a mut Pack<T, L>,
+    moved: usize,
+}
+
+impl<T: Packable, L: EntryLayout> Drop for Unmapped<'_, T, L> {
+    fn drop(&mut self) {
+        let pack = &mut *self.pack;
+        let len = pack.len();
+        let rest: Vec<_> = (self.moved..len)
+            .filter_map(|position| {
+                let entry = pack.entries.get(position)?;
+                let variant = entry.variant;
+                variant
+                    .needs_drop()
+                    .then(|| (variant, pack.payload_mut(position, entry)))
+            })
+            .collect();
+        // Leak rather than double drop if a destructor panics. The entries
+        // keep their memory, so inline payloads stay where they are.
+        pack.entries.truncate(0);
+        for variant in bucket_variants::<T>() {
+            bucket!(pack, variant).set_len(0);
+        }
+        for (variant, data) in rest {
+            unsafe { T::drop_in_place(variant, data, 1) };
+        }
+    }
+}
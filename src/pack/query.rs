@@ -0,0 +1,138 @@
+use super::{traits::clone_element, Entry, EntryLayout, EntryStore, Pack};
+use crate::{Packable, Variant, VariantSet};
+
+/// A filtered view over the elements of a [`Pack`]
+///
+/// Created by [`Pack::query`]. Variant restrictions from [`Query::only`] are
+/// checked against the stored tags before any element is read, and
+/// [`Query::count`] scans only the buckets of the selected variants, going
+/// through the entries of variants without a bucket of their own.
+pub struct Query<'a, T: Packable, F, L: EntryLayout = u32> {
+    pack: &'a Pack<T, L>,
+    only: VariantSet<T>,
+    filter: F,
+}
+
+impl<T: Packable, L: EntryLayout> Pack<T, L> {
+    /// Starts a query over every element. See [`Query`].
+    pub fn query(&self) -> Query<'_, T, impl FnMut(&T::Ref<'_>) -> bool, L> {
+        Query {
+            pack: self,
+            only: VariantSet::all(),
+            filter: |_: &T::Ref<'_>| true,
+        }
+    }
+}
+
+impl<'a, T: Packable, F: FnMut(&T::Ref<'a>) -> bool, L: EntryLayout> Query<'a, T, F, L> {
+    /// Restricts the query to the given variants
+    pub fn only(mut self, variants: &VariantSet<T>) -> Self {
+        self.only.intersect_with(variants);
+        self
+    }
+
+    /// Restricts the query to elements matching the predicate
+    pub fn filter(
+        self,
+        mut predicate: impl FnMut(&T::Ref<'a>) -> bool,
+    ) -> Query<'a, T, impl FnMut(&T::Ref<'a>) -> bool, L> {
+        let Self {
+            pack,
+            only,
+            mut filter,
+        } = self;
+        Query {
+            pack,
+            only,
+            filter: move |element: &T::Ref<'a>| filter(element) && predicate(element),
+        }
+    }
+
+    /// Iterates over the positions, entries and matching elements in
+    /// insertion order
+    fn scan(self) -> impl Iterator<Item = (usize, Entry<T>, T::Ref<'a>)> + use<'a, T, F, L> {
+        let Self {
+            pack,
+            only,
+            mut filter,
+        } = self;
+        pack.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, entry)| only.contains(entry.variant))
+            .map(move |(position, entry)| {
+                let element = unsafe { T::read_ref(entry.variant, pack.payload(position, entry)) };
+                (position, entry, element)
+            })
+            .filter(move |(_, _, element)| filter(element))
+    }
+
+    /// Iterates over the matching elements in insertion order
+    pub fn iter(self) -> impl Iterator<Item = T::Ref<'a>> + use<'a, T, F, L> {
+        self.scan().map(|(_, _, element)| element)
+    }
+
+    /// Iterates over the positions of the matching elements
+    pub fn positions(self) -> impl Iterator<Item = usize> + use<'a, T, F, L> {
+        self.scan().map(|(position, ..)| position)
+    }
+
+    /// Converts each matching element in insertion order
+    pub fn map<U, M: FnMut(T::Ref<'a>) -> U>(
+        self,
+        f: M,
+    ) -> impl Iterator<Item = U> + use<'a, T, F, L, U, M> {
+        self.iter().map(f)
+    }
+
+    /// Folds the matching elements in insertion order
+    pub fn fold<B>(self, init: B, f: impl FnMut(B, T::Ref<'a>) -> B) -> B {
+        self.iter().fold(init, f)
+    }
+
+    /// Collects the matching elements in insertion order
+    pub fn collect<C: FromIterator<T::Ref<'a>>>(self) -> C {
+        self.iter().collect()
+    }
+
+    /// Clones the matching elements in insertion order into a new collection
+    pub fn to_pack(self) -> Pack<T, L>
+    where
+        T: Clone,
+    {
+        let pack = self.pack;
+        let mut out = Pack::empty();
+        for (position, entry, _) in self.scan() {
+            out.push(unsafe { clone_element(entry.variant, pack.payload(position, entry)) });
+        }
+        out
+    }
+
+    /// Counts the matching elements by scanning only the selected buckets
+    pub fn count(self) -> usize {
+        let Self {
+            pack,
+            only,
+            mut filter,
+        } = self;
+        only.iter()
+            .map(|variant| {
+                let mut matches = |data| filter(&unsafe { T::read_ref(variant, data) });
+                if variant.shares_bucket() || Pack::<T, L>::is_inline(variant) {
+                    pack.entries
+                        .iter()
+                        .enumerate()
+                        .filter(|&(position, entry)| {
+                            entry.variant == variant && matches(pack.payload(position, entry))
+                        })
+                        .count()
+                } else {
+                    let bucket = bucket_ref!(pack, variant);
+                    (0..bucket.len())
+                        .filter(|&i| matches(bucket.get(i)))
+                        .count()
+                }
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,150 @@
+use super::{EntryLayout, EntryStore, Pack};
+use crate::{Packable, Variant};
+
+/// The number of elements between rows of the rank directory
+const BLOCK: usize = 256;
+
+/// Answers rank and select queries over the elements of a [`Pack`]
+///
+/// Created by [`Pack::ranks`], which builds a directory of the number of
+/// elements of each variant before the start of every block of 256 elements.
+/// Rank queries then scan at most one block, and select queries binary search
+/// the directory before scanning one block, so both are O(log n) with a
+/// constant up to the block size.
+pub struct Ranks<'a, T: Packable, L: EntryLayout = u32> {
+    pack: &'a Pack<T, L>,
+    index: RankIndex,
+}
+
+/// The rank directory of a [`Pack`]
+struct RankIndex {
+    /// The number of elements of each variant before the start of each
+    /// block, one row of `VARIANT_COUNT` counts per block
+    rows: Vec<usize>,
+    /// The number of elements of each variant
+    totals: Vec<usize>,
+}
+
+impl RankIndex {
+    fn build<T: Packable, L: EntryLayout>(pack: &Pack<T, L>) -> Self {
+        let mut rows = Vec::with_capacity(pack.len().div_ceil(BLOCK) * T::VARIANT_COUNT);
+        let mut totals = vec![0; T::VARIANT_COUNT];
+        for (position, variant) in pack.entries.variants_from(0).enumerate() {
+            if position.is_multiple_of(BLOCK) {
+                rows.extend_from_slice(&totals);
+            }
+            totals[variant.as_index()] += 1;
+        }
+        Self { rows, totals }
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Pack<T, L> {
+    /// Builds an index for rank and select queries, which takes one pass
+    /// over the entries and borrows the collection for as long as it is
+    /// kept. See [`Ranks`].
+    pub fn ranks(&self) -> Ranks<'_, T, L> {
+        Ranks {
+            pack: self,
+            index: RankIndex::build(self),
+        }
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Ranks<'_, T, L> {
+    /// Gets the logical position of the `k`-th element of the given variant
+    pub fn nth_of_variant(&self, variant: T::Variant, k: usize) -> Option<usize> {
+        let i = variant.as_index();
+        self.select(k, |row| row[i], |other| other == variant)
+    }
+
+    /// Counts the elements of the given variant before logical position
+    /// `position`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `position > len`
+    pub fn rank(&self, variant: T::Variant, position: usize) -> usize {
+        assert!(position <= self.pack.len());
+        let block = position / BLOCK;
+        let i = variant.as_index();
+        if block == self.blocks() {
+            // The position is the end of a full last block
+            return self.index.totals[i];
+        }
+        let scanned = self
+            .pack
+            .entries
+            .variants_from(block * BLOCK)
+            .take(position - block * BLOCK)
+            .filter(|&other| other == variant)
+            .count();
+        self.row(block)[i] + scanned
+    }
+
+    /// Gets the logical position of the element at `bucket_index` in the
+    /// bucket of the given variant
+    pub fn position_of(&self, variant: T::Variant, bucket_index: usize) -> Option<usize> {
+        // Buckets are kept in the same order as their entries
+        if !variant.shares_bucket() {
+            return self.nth_of_variant(variant, bucket_index);
+        }
+        let bucket = variant.bucket_index();
+        let sharing = |other: &T::Variant| other.bucket_index() == bucket;
+        self.select(
+            bucket_index,
+            |row| {
+                <T::Variant as Variant>::all()
+                    .filter(sharing)
+                    .map(|other| row[other.as_index()])
+                    .sum()
+            },
+            |other| sharing(&other),
+        )
+    }
+
+    /// Gets the logical position of the `k`-th element whose variant
+    /// matches, given how many of them each row of the directory counts
+    fn select(
+        &self,
+        k: usize,
+        count: impl Fn(&[usize]) -> usize,
+        matches: impl Fn(T::Variant) -> bool,
+    ) -> Option<usize> {
+        if k >= count(&self.index.totals) {
+            return None;
+        }
+        // Find the last block with at most k matching elements before it,
+        // which the first block always has
+        let (mut block, mut end) = (0, self.blocks());
+        while end - block > 1 {
+            let mid = (block + end) / 2;
+            if count(self.row(mid)) <= k {
+                block = mid;
+            } else {
+                end = mid;
+            }
+        }
+        let mut left = k - count(self.row(block));
+        for (offset, variant) in self.pack.entries.variants_from(block * BLOCK).enumerate() {
+            if matches(variant) {
+                if left == 0 {
+                    return Some(block * BLOCK + offset);
+                }
+                left -= 1;
+            }
+        }
+        unreachable!("The totals count every element")
+    }
+
+    /// Gets the number of rows in the directory
+    fn blocks(&self) -> usize {
+        self.pack.len().div_ceil(BLOCK)
+    }
+
+    /// Gets the counts of each variant before the start of a block
+    fn row(&self, block: usize) -> &[usize] {
+        let start = block * T::VARIANT_COUNT;
+        &self.index.rows[start..start + T::VARIANT_COUNT]
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    byte_vec::{ByteVec, WrapVec},
+    Packable, Variant,
+};
+
+// Records never move while they are in use, so they are appended to the tail
+// bucket and left behind when their element is removed, unless no record
+// comes after them. Once the bucket is full, the records still pointed to are
+// packed into a new bucket instead of growing the old one. Every record starts
+// at a multiple of the alignment of the bucket, so the records tile it with
+// less than one alignment of padding after each.
+
+/// Gets the alignment of the tail bucket, which suits the records of every
+/// variant
+fn records_align<T: Packable>() -> usize {
+    <T::Variant as Variant>::all()
+        .filter_map(|variant| variant.tail_layout())
+        .map(|layout| layout.align)
+        .max()
+        .unwrap_or(1)
+}
+
+/// Reserves a record at the end of the tail bucket for the tail of `element`,
+/// returning `None` if its variant has no tail
+///
+/// The tail bucket comes after the bucket of each variant, and variants with
+/// a tail have buckets of their own holding only initialized pointers to
+/// their records.
+pub(super) fn reserve_record<T: Packable>(
+    buckets: &mut T::Buckets,
+    element: &T,
+) -> Option<*mut u8> {
+    let layout = element.variant().tail_layout()?;
+    let size = layout.record_size(element.tail_len());
+    let align = records_align::<T>();
+    let (buckets, records) = buckets.as_mut().split_at_mut(T::VARIANT_COUNT);
+    let records = &mut records[0];
+
+    let fits = {
+        let records = unsafe { WrapVec::new(records, 1, align) };
+        records.len().next_multiple_of(align) + size <= records.cap()
+    };
+    if !fits {
+        relocate::<T>(buckets, records, align, |live| {
+            ((live + size + align) * 2).max(64)
+        });
+    }
+    let mut records = unsafe { WrapVec::new(records, 1, align) };
+    let start = records.len().next_multiple_of(align);
+    records.set_len(start + size);
+    Some(records.get_mut(start))
+}
+
+/// Writes `element` to the slot at `dst`, first pointing the slot to the
+/// record reserved for its tail, if any
+///
+/// # Safety
+///
+/// See [`Packable::write`]. `record` must come from [`reserve_record`] for
+/// `element`.
+pub(super) unsafe fn write_element<T: Packable>(element: T, record: Option<*mut u8>, dst: *mut u8) {
+    if let Some(record) = record {
+        unsafe { dst.cast::<*mut u8>().write(record) };
+    }
+    unsafe { element.write(dst) };
+}
+
+/// Packs the records still in use into a tail bucket of their exact size
+pub(super) fn shrink_records<T: Packable>(buckets: &mut T::Buckets) {
+    let align = records_align::<T>();
+    let (buckets, records) = buckets.as_mut().split_at_mut(T::VARIANT_COUNT);
+    if let Some(records) = records.first_mut() {
+        relocate::<T>(buckets, records, align, |live| live);
+    }
+}
+
+/// Packs the records pointed to from the buckets of the variants into a new
+/// tail bucket, whose capacity `capacity` gets from the size of the records
+fn relocate<T: Packable>(
+    buckets: &mut [ByteVec],
+    records: &mut ByteVec,
+    align: usize,
+    capacity: impl FnOnce(usize) -> usize,
+) {
+    let mut slots = Vec::new();
+    let mut live: usize = 0;
+    for variant in <T::Variant as Variant>::all() {
+        let Some(layout) = variant.tail_layout() else {
+            continue;
+        };
+        let (size, slot_align) = variant.size_align();
+        let mut bucket =
+            unsafe { WrapVec::new(&mut buckets[variant.as_index()], size, slot_align) };
+        for index in 0..bucket.len() {
+            let slot = bucket.get_mut(index).cast::<*mut u8>();
+            let record = unsafe { slot.read() };
+            let len = unsafe { record.cast::<usize>().read() };
+            let size = layout.record_size(len);
+            live = live.next_multiple_of(align) + size;
+            slots.push((slot, record, size));
+        }
+    }
+
+    let mut moved = ByteVec::new();
+    let mut packed = unsafe { WrapVec::new(&mut moved, 1, align) };
+    packed.alloc(capacity(live));
+    for (slot, record, size) in slots {
+        let start = packed.len().next_multiple_of(align);
+        packed.set_len(start + size);
+        let dst = packed.get_mut(start);
+        unsafe {
+            dst.copy_from_nonoverlapping(record, size);
+            slot.write(dst);
+        }
+    }
+
+    std::mem::swap(records, &mut moved);
+    // The records still in use were moved bitwise into the new bucket
+    let mut old = unsafe { WrapVec::new(&mut moved, 1, align) };
+    old.set_len(0);
+    old.dealloc();
+}
+
+/// Gives the records of elements that are being removed back to the tail
+/// bucket for as long as no other record comes after them, like removing the
+/// last elements of a bucket does
+///
+/// # Safety
+///
+/// Each of `slots` must point to an initialized slot of its variant whose
+/// record is not used again, other than by dropping it before anything is
+/// pushed.
+pub(super) unsafe fn release_records<T: Packable>(
+    buckets: &mut T::Buckets,
+    slots: impl IntoIterator<Item = (T::Variant, *const u8)>,
+) {
+    let align = records_align::<T>();
+    let Some(records) = buckets.as_mut().get_mut(T::VARIANT_COUNT) else {
+        return;
+    };
+    let mut records = unsafe { WrapVec::new(records, 1, align) };
+    let base = records.as_mut_ptr().cast_const();
+    let mut released: Vec<_> = slots
+        .into_iter()
+        .filter_map(|(variant, slot)| {
+            let layout = variant.tail_layout()?;
+            let record = unsafe { slot.cast::<*const u8>().read() };
+            let len = unsafe { record.cast::<usize>().read() };
+            let start = unsafe { record.offset_from(base) } as usize;
+            Some((start, start + layout.record_size(len)))
+        })
+        .collect();
+    released.sort_unstable();
+    // Any record after one that is released would start at or past the end
+    // of its padding
+    while let Some(&(start, end)) = released.last() {
+        if end.next_multiple_of(align) < records.len() {
+            break;
+        }
+        records.set_len(start);
+        released.pop();
+    }
+}
+
+/// Frees the tail bucket once no element points into it anymore
+pub(super) fn dealloc_records<T: Packable>(buckets: &mut T::Buckets) {
+    if let Some(records) = buckets.as_mut().get_mut(T::VARIANT_COUNT) {
+        let mut records = unsafe { WrapVec::new(records, 1, records_align::<T>()) };
+        records.set_len(0);
+        records.dealloc();
+    }
+}
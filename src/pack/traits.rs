@@ -0,0 +1,170 @@
+use super::{bucket_variants, unbox_slot, EntryLayout, EntryStore, Pack};
+use crate::{Packable, Variant};
+use std::{
+    alloc::{self, Layout},
+    cmp::Ordering,
+    fmt::{self, Debug, Formatter},
+    hash::{Hash, Hasher},
+    mem::ManuallyDrop,
+};
+
+impl<T: Packable + Clone, L: EntryLayout> Clone for Pack<T, L> {
+    fn clone(&self) -> Self {
+        let mut out = Self::default();
+        if T::IS_COPY && !<T::Variant as Variant>::all().any(|v| v.is_boxed()) {
+            // Copy payloads are plain data, so each bucket can be duplicated
+            // with a single allocation. Boxes would be shared by the copy.
+            for variant in bucket_variants::<T>() {
+                out.buckets.as_mut()[variant.bucket_index()] =
+                    bucket_ref!(self, variant).copy_to_new();
+            }
+            out.entries.clone_from(&self.entries);
+        } else {
+            out.entries.reserve(self.len());
+            for (position, entry) in self.entries.iter().enumerate() {
+                let data = self.payload(position, entry);
+                out.push(unsafe { clone_element(entry.variant, data) });
+            }
+        }
+        out
+    }
+}
+
+/// Clones the element in a slot
+///
+/// # Safety
+///
+/// `slot` must point to an initialized slot of `variant`.
+pub(super) unsafe fn clone_element<T: Packable + Clone>(variant: T::Variant, slot: *const u8) -> T {
+    // The original stays in the pack, so only the copy read out of it must
+    // not be dropped
+    let element = ManuallyDrop::new(unsafe { read_copy::<T>(variant, slot) });
+    let clone = T::clone(&element);
+    unsafe { ManuallyDrop::into_inner(element).forget_copy() };
+    clone
+}
+
+/// Reads a bitwise copy of the element in a slot, leaving the slot as it was
+///
+/// # Safety
+///
+/// `slot` must point to an initialized slot of `variant`. The copy shares
+/// ownership with the original, so it must not be dropped.
+unsafe fn read_copy<T: Packable>(variant: T::Variant, slot: *const u8) -> T {
+    if !variant.is_boxed() {
+        return unsafe { T::read(variant, slot) };
+    }
+    // Reading a boxed element frees its box, so read from a copy of the box
+    let (size, align) = variant.payload_size_align();
+    let layout = Layout::from_size_align(size, align).unwrap();
+    let boxed = if size == 0 {
+        std::ptr::without_provenance_mut(align)
+    } else {
+        let boxed = unsafe { alloc::alloc(layout) };
+        if boxed.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        boxed
+    };
+    unsafe {
+        boxed.copy_from_nonoverlapping(unbox_slot(variant, slot), size);
+        T::read(variant, (&raw const boxed).cast())
+    }
+}
+
+impl<T: Packable, L: EntryLayout> FromIterator<T> for Pack<T, L> {
+    fn from_iter<Iter: IntoIterator<Item = T>>(iter: Iter) -> Self {
+        let mut out = Self::default();
+        out.extend(iter);
+        out
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Extend<T> for Pack<T, L> {
+    fn extend<Iter: IntoIterator<Item = T>>(&mut self, iter: Iter) {
+        let iter = iter.into_iter();
+        self.entries.reserve(iter.size_hint().0);
+        for element in iter {
+            self.push(element);
+        }
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Debug for Pack<T, L>
+where
+    for<'a> T::Ref<'a>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Packable, L: EntryLayout> PartialEq for Pack<T, L>
+where
+    for<'a> T::Ref<'a>: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Eq for Pack<T, L> where for<'a> T::Ref<'a>: Eq {}
+
+impl<T: Packable, L: EntryLayout> PartialEq<[T]> for Pack<T, L>
+where
+    for<'a> T::Ref<'a>: PartialEq,
+{
+    fn eq(&self, other: &[T]) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter().map(T::to_ref))
+    }
+}
+
+impl<T: Packable, L: EntryLayout> PartialEq<Vec<T>> for Pack<T, L>
+where
+    for<'a> T::Ref<'a>: PartialEq,
+{
+    fn eq(&self, other: &Vec<T>) -> bool {
+        *self == **other
+    }
+}
+
+impl<T: Packable, L: EntryLayout, const N: usize> PartialEq<[T; N]> for Pack<T, L>
+where
+    for<'a> T::Ref<'a>: PartialEq,
+{
+    fn eq(&self, other: &[T; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T: Packable, L: EntryLayout> PartialOrd for Pack<T, L>
+where
+    for<'a> T::Ref<'a>: PartialOrd,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Ord for Pack<T, L>
+where
+    for<'a> T::Ref<'a>: Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Packable, L: EntryLayout> Hash for Pack<T, L>
+where
+    for<'a> T::Ref<'a>: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Prefix the length like slices do so that nested collections hash
+        // unambiguously
+        state.write_usize(self.len());
+        for element in self.iter() {
+            element.hash(state);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{Packable, Variant};
+use std::{
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+};
+
+/// A map with exactly one value for each variant of a [`Packable`] enum
+///
+/// Values are stored inline in an array indexed by [`Variant::as_index`], so
+/// the map never allocates.
+pub struct VariantMap<T: Packable, V> {
+    values: T::Array<V>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Packable, V> VariantMap<T, V> {
+    /// Creates a map by calling `f` for each variant in index order
+    pub fn from_fn(f: impl FnMut(T::Variant) -> V) -> Self {
+        let mut values = <T::Variant as Variant>::all().map(f);
+        Self {
+            values: T::array_from_fn(|_| values.next().expect("one value per variant")),
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterates over the variants and their values in index order
+    pub fn iter(&self) -> impl Iterator<Item = (T::Variant, &V)> {
+        <T::Variant as Variant>::all().zip(self.values.as_ref())
+    }
+
+    /// Mutably iterates over the variants and their values in index order
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (T::Variant, &mut V)> {
+        <T::Variant as Variant>::all().zip(self.values.as_mut())
+    }
+
+    /// Iterates over the values in index order
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.values.as_ref().iter()
+    }
+
+    /// Converts each value
+    pub fn map<W>(self, f: impl FnMut(V) -> W) -> VariantMap<T, W> {
+        let mut values = self.values.into_iter().map(f);
+        VariantMap {
+            values: T::array_from_fn(|_| values.next().expect("one value per variant")),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Packable, V> Index<T::Variant> for VariantMap<T, V> {
+    type Output = V;
+
+    fn index(&self, variant: T::Variant) -> &Self::Output {
+        &self.values.as_ref()[variant.as_index()]
+    }
+}
+
+impl<T: Packable, V> IndexMut<T::Variant> for VariantMap<T, V> {
+    fn index_mut(&mut self, variant: T::Variant) -> &mut Self::Output {
+        &mut self.values.as_mut()[variant.as_index()]
+    }
+}
+
+impl<T: Packable, V: Default> Default for VariantMap<T, V> {
+    fn default() -> Self {
+        Self::from_fn(|_| V::default())
+    }
+}
+
+impl<T: Packable, V: Clone> Clone for VariantMap<T, V> {
+    fn clone(&self) -> Self {
+        let values = self.values.as_ref();
+        Self {
+            values: T::array_from_fn(|i| values[i].clone()),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Packable, V: PartialEq> PartialEq for VariantMap<T, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.values.as_ref() == other.values.as_ref()
+    }
+}
+
+impl<T: Packable, V: Eq> Eq for VariantMap<T, V> {}
+
+impl<T: Packable, V: Debug> Debug for VariantMap<T, V>
+where
+    T::Variant: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
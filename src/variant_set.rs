@@ -0,0 +1,232 @@
+use crate::{Packable, Variant};
+use std::{
+    fmt::{self, Debug, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::{BitAnd, BitOr, BitXor, Not, Sub},
+};
+
+/// A set of the variants of a [`Packable`] enum
+///
+/// Stored inline as a bitset indexed by [`Variant::as_index`], so sets never
+/// allocate and are [`Copy`].
+pub struct VariantSet<T: Packable> {
+    bits: T::Bits,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Packable> VariantSet<T> {
+    /// Creates an empty set
+    pub const fn new() -> Self {
+        Self {
+            bits: T::EMPTY_BITS,
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates a set containing only the given variant
+    pub fn single(variant: T::Variant) -> Self {
+        let mut out = Self::new();
+        out.insert(variant);
+        out
+    }
+
+    /// Creates a set containing every variant
+    pub fn all() -> Self {
+        <T::Variant as Variant>::all().collect()
+    }
+
+    /// Whether the set contains the variant
+    pub fn contains(&self, variant: T::Variant) -> bool {
+        let i = variant.as_index();
+        self.bits.as_ref()[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Adds a variant to the set, returning whether it was newly inserted
+    pub fn insert(&mut self, variant: T::Variant) -> bool {
+        let i = variant.as_index();
+        let word = &mut self.bits.as_mut()[i / 64];
+        let bit = 1 << (i % 64);
+        let inserted = *word & bit == 0;
+        *word |= bit;
+        inserted
+    }
+
+    /// Removes a variant from the set, returning whether it was present
+    pub fn remove(&mut self, variant: T::Variant) -> bool {
+        let i = variant.as_index();
+        let word = &mut self.bits.as_mut()[i / 64];
+        let bit = 1 << (i % 64);
+        let removed = *word & bit != 0;
+        *word &= !bit;
+        removed
+    }
+
+    /// Removes every variant not contained in `other`
+    pub fn intersect_with(&mut self, other: &Self) {
+        *self = self.zip_with(other, |a, b| a & b);
+    }
+
+    /// Whether the set contains no variants
+    pub fn is_empty(&self) -> bool {
+        self.bits.as_ref().iter().all(|&word| word == 0)
+    }
+
+    /// Counts the variants in the set
+    pub fn len(&self) -> usize {
+        let ones: u32 = self
+            .bits
+            .as_ref()
+            .iter()
+            .map(|word| word.count_ones())
+            .sum();
+        ones as usize
+    }
+
+    /// Creates a set of the variants in either set
+    pub fn union(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a | b)
+    }
+
+    /// Creates a set of the variants in both sets
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a & b)
+    }
+
+    /// Creates a set of the variants in `self` but not in `other`
+    pub fn difference(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a & !b)
+    }
+
+    /// Creates a set of the variants in exactly one of the sets
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a ^ b)
+    }
+
+    /// Creates a set of the variants not in `self`
+    pub fn complement(&self) -> Self {
+        Self::all().difference(self)
+    }
+
+    /// Whether every variant in `self` is also in `other`
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.difference(other).is_empty()
+    }
+
+    /// Whether every variant in `other` is also in `self`
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Whether the sets have no variants in common
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        self.intersection(other).is_empty()
+    }
+
+    /// Combines the words of both sets
+    fn zip_with(&self, other: &Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        let mut out = *self;
+        for (word, &other) in out.bits.as_mut().iter_mut().zip(other.bits.as_ref()) {
+            *word = f(*word, other);
+        }
+        out
+    }
+
+    /// Iterates over the variants in the set in index order
+    pub fn iter(&self) -> impl Iterator<Item = T::Variant> + '_ {
+        <T::Variant as Variant>::all().filter(|&variant| self.contains(variant))
+    }
+}
+
+impl<T: Packable> PartialEq for VariantSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits.as_ref() == other.bits.as_ref()
+    }
+}
+
+impl<T: Packable> Eq for VariantSet<T> {}
+
+impl<T: Packable> Hash for VariantSet<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bits.as_ref().hash(state);
+    }
+}
+
+impl<T: Packable> BitOr for &VariantSet<T> {
+    type Output = VariantSet<T>;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl<T: Packable> BitAnd for &VariantSet<T> {
+    type Output = VariantSet<T>;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.intersection(rhs)
+    }
+}
+
+impl<T: Packable> BitXor for &VariantSet<T> {
+    type Output = VariantSet<T>;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        self.symmetric_difference(rhs)
+    }
+}
+
+impl<T: Packable> Sub for &VariantSet<T> {
+    type Output = VariantSet<T>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.difference(rhs)
+    }
+}
+
+impl<T: Packable> Not for &VariantSet<T> {
+    type Output = VariantSet<T>;
+
+    fn not(self) -> Self::Output {
+        self.complement()
+    }
+}
+
+impl<T: Packable> Default for VariantSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Packable> Clone for VariantSet<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Packable> Copy for VariantSet<T> {}
+
+impl<T: Packable> Debug for VariantSet<T>
+where
+    T::Variant: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Packable> FromIterator<T::Variant> for VariantSet<T> {
+    fn from_iter<I: IntoIterator<Item = T::Variant>>(iter: I) -> Self {
+        let mut out = Self::new();
+        out.extend(iter);
+        out
+    }
+}
+
+impl<T: Packable> Extend<T::Variant> for VariantSet<T> {
+    fn extend<I: IntoIterator<Item = T::Variant>>(&mut self, iter: I) {
+        for variant in iter {
+            self.insert(variant);
+        }
+    }
+}